@@ -0,0 +1,185 @@
+//! Requests per second the `testroute` binary serves, e.g. `cargo bench`.
+//! Each scenario starts the server on a route file and keeps `CONNECTIONS`
+//! keep-alive connections busy for `DURATION`.
+//!
+//! With `TESTROUTE_BASELINE` set to another `testroute` binary, e.g. one
+//! built from `main`, each scenario is run against it too and compared.
+
+use std::{
+    env,
+    net::{SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{Method, Request},
+};
+use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+
+const CONNECTIONS: usize = 64;
+const DURATION: Duration = Duration::from_secs(3);
+
+struct Server(Child);
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// A scenario: the request hammered, and the flags the server is started with.
+struct Scenario {
+    name: &'static str,
+    method: Method,
+    path: &'static str,
+    body: Vec<u8>,
+    flags: &'static [&'static str],
+}
+
+/// `None` when the binary exits, e.g. a baseline that doesn't know a flag yet.
+fn start(binary: &Path, routes: &Path, flags: &[&str]) -> Option<(Server, SocketAddr)> {
+    let address = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let child = Command::new(binary)
+        .arg("--import")
+        .arg(routes)
+        .args(["--port", &address.port().to_string()])
+        .args(flags)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut server = Server(child);
+
+    let started = Instant::now();
+    while TcpStream::connect(address).is_err() {
+        if server.0.try_wait().unwrap().is_some() {
+            return None;
+        }
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "testroute didn't start"
+        );
+        thread::sleep(Duration::from_millis(20));
+    }
+    Some((server, address))
+}
+
+async fn hammer(address: SocketAddr, scenario: &Scenario) -> f64 {
+    let client: Client<_, Body> = Client::builder(TokioExecutor::new()).build_http();
+    let uri: hyper::Uri = format!("http://{address}{}", scenario.path)
+        .parse()
+        .unwrap();
+    let started = Instant::now();
+
+    let workers = (0..CONNECTIONS).map(|_| {
+        let (client, uri) = (client.clone(), uri.clone());
+        let (method, body) = (scenario.method.clone(), scenario.body.clone());
+        tokio::spawn(async move {
+            let mut requests = 0u64;
+            while started.elapsed() < DURATION {
+                let request = Request::builder()
+                    .method(method.clone())
+                    .uri(uri.clone())
+                    .body(Body::from(body.clone()))
+                    .unwrap();
+                let response = client.request(request).await.unwrap();
+                assert!(response.status().is_success());
+                to_bytes(Body::new(response.into_body()), usize::MAX)
+                    .await
+                    .unwrap();
+                requests += 1;
+            }
+            requests
+        })
+    });
+
+    let mut requests = 0;
+    for worker in workers.collect::<Vec<_>>() {
+        requests += worker.await.unwrap();
+    }
+    requests as f64 / started.elapsed().as_secs_f64()
+}
+
+#[tokio::main]
+async fn main() {
+    let directory = std::env::temp_dir().join(format!("testroute-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+
+    let user = r#"{"id": 1, "name": "Foo", "email": "foo@example.com", "tags": ["a", "b", "c"]}"#;
+    let users = format!("[{}]", vec![user; 32].join(","));
+    std::fs::write(directory.join("users.json"), &users).unwrap();
+    let routes = directory.join("routes.http");
+    std::fs::write(
+        &routes,
+        format!(
+            "GET /api/users 200\nHeader=Content-Type: application/json\n\n{users}\n\n\
+             GET /api/users/file 200\nFile=users.json\n\nPOST /api/users 201\n"
+        ),
+    )
+    .unwrap();
+
+    let scenario = |name, method, path, body, flags| Scenario {
+        name,
+        method,
+        path,
+        body,
+        flags,
+    };
+    let scenarios = [
+        scenario("inline body", Method::GET, "/api/users", Vec::new(), &[]),
+        scenario("file body", Method::GET, "/api/users/file", Vec::new(), &[]),
+        scenario(
+            "file body, --cache-files",
+            Method::GET,
+            "/api/users/file",
+            Vec::new(),
+            &["--cache-files"],
+        ),
+        // What the journal costs: every request body is read before the route answers.
+        scenario(
+            "16 KiB request body",
+            Method::POST,
+            "/api/users",
+            vec![b'x'; 16 * 1024],
+            &[],
+        ),
+    ];
+
+    let current = PathBuf::from(env!("CARGO_BIN_EXE_testroute"));
+    let baseline = env::var_os("TESTROUTE_BASELINE").map(PathBuf::from);
+    match &baseline {
+        Some(baseline) => println!("{:<28} {:>16} {:>16}", "", "requests/s", baseline.display()),
+        None => println!("{:<28} {:>16}", "", "requests/s"),
+    }
+
+    for scenario in &scenarios {
+        let (server, address) = start(&current, &routes, scenario.flags).expect("testroute exited");
+        let throughput = hammer(address, scenario).await;
+        drop(server);
+
+        let compared = match &baseline {
+            Some(baseline) => match start(baseline, &routes, scenario.flags) {
+                Some((_server, address)) => {
+                    let before = hammer(address, scenario).await;
+                    format!(
+                        " {before:>16.0} ({:+.0}%)",
+                        (throughput / before - 1.0) * 100.0
+                    )
+                }
+                None => format!(" {:>16}", "unsupported"),
+            },
+            None => String::new(),
+        };
+        println!("{:<28} {throughput:>16.0}{compared}", scenario.name);
+    }
+
+    std::fs::remove_dir_all(directory).unwrap();
+}
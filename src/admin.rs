@@ -0,0 +1,351 @@
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use std::path::{Component, Path};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    app_requirements::ApplicationRequirements,
+    journal::{JournalEntry, JournalQuery},
+    route_pattern::{InvalidPattern, PathPattern},
+    server::MockServer,
+    state::ServerState,
+    tokenizer::{self, ast::ParseError},
+    verify::{Verification, VerificationResult},
+};
+
+/// Prefix reserved for testroute's own endpoints; mocks can't be served under it.
+pub const ADMIN_PREFIX: &str = "/__testroute";
+
+#[derive(Error, Debug)]
+pub enum AdminError {
+    #[error("Invalid .http route definition: {0}")]
+    InvalidHttpFile(#[from] ParseError),
+    #[error("Invalid JSON route definition: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+    #[error("Route already exists: {0}")]
+    DuplicateRoute(String),
+    #[error("Paths under {ADMIN_PREFIX} are reserved: {0}")]
+    ReservedPath(String),
+    #[error(transparent)]
+    InvalidPath(#[from] InvalidPattern),
+    #[error("Invalid HTTP status code, expected 100 to 999: {0}")]
+    InvalidStatus(u16),
+    #[error("Response files must be relative paths inside the directory of the imported .http file: {0}")]
+    FileOutsideRoot(String),
+    #[error("No route matches the given method and path")]
+    RouteNotFound,
+}
+
+impl IntoResponse for AdminError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            AdminError::DuplicateRoute(_) => StatusCode::CONFLICT,
+            AdminError::RouteNotFound => StatusCode::NOT_FOUND,
+            _ => StatusCode::BAD_REQUEST,
+        };
+
+        (
+            status,
+            Json(serde_json::json!({ "error": self.to_string() })),
+        )
+            .into_response()
+    }
+}
+
+pub fn router(server: MockServer) -> Router {
+    Router::new()
+        .route("/state", get(show_state))
+        .route("/state/reset", post(reset_state))
+        .route("/requests", get(list_requests).delete(clear_requests))
+        .route("/verify", post(verify_requests))
+        .route(
+            "/routes",
+            get(list_routes)
+                .post(create_routes)
+                .put(replace_routes)
+                .delete(delete_routes),
+        )
+        .with_state(server)
+}
+
+async fn show_state(State(server): State<MockServer>) -> Json<ServerState> {
+    Json(server.store.snapshot())
+}
+
+async fn reset_state(State(server): State<MockServer>) -> Json<ServerState> {
+    let state = server.store.reset();
+    server.reload();
+    Json(state)
+}
+
+async fn list_requests(
+    State(server): State<MockServer>,
+    Query(query): Query<JournalQuery>,
+) -> Json<Vec<JournalEntry>> {
+    Json(server.store.lock().journal.query(&query))
+}
+
+async fn clear_requests(State(server): State<MockServer>) -> StatusCode {
+    server.store.lock().journal.clear();
+    StatusCode::NO_CONTENT
+}
+
+/// Answers 417 Expectation Failed when the count is off, so `curl --fail` works.
+async fn verify_requests(
+    State(server): State<MockServer>,
+    Json(verification): Json<Verification>,
+) -> (StatusCode, Json<VerificationResult>) {
+    let result = verification.run(&server.store.lock().journal);
+    let status = if result.passed {
+        StatusCode::OK
+    } else {
+        StatusCode::EXPECTATION_FAILED
+    };
+
+    (status, Json(result))
+}
+
+async fn list_routes(State(server): State<MockServer>) -> Json<Vec<ApplicationRequirements>> {
+    Json(server.routes())
+}
+
+/// Adds new routes, refusing any method and path that is already served.
+async fn create_routes(
+    State(server): State<MockServer>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<(StatusCode, Json<Vec<ApplicationRequirements>>), AdminError> {
+    let created = parse_routes(&headers, body, &server.config().file_root)?;
+
+    server.update_routes(|routes| {
+        for (index, route) in created.iter().enumerate() {
+            let taken = routes
+                .iter()
+                .chain(&created[..index])
+                .any(|existing| same_route(existing, route));
+            if taken {
+                return Err(AdminError::DuplicateRoute(route.route_key()));
+            }
+        }
+
+        routes.extend(created.iter().cloned());
+        Ok(())
+    })?;
+
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+/// Adds new routes, replacing the ones already served on the same method and path.
+async fn replace_routes(
+    State(server): State<MockServer>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Json<Vec<ApplicationRequirements>>, AdminError> {
+    let replacements = parse_routes(&headers, body, &server.config().file_root)?;
+
+    server.update_routes(|routes| {
+        for replacement in &replacements {
+            match routes
+                .iter_mut()
+                .find(|existing| same_route(existing, replacement))
+            {
+                Some(existing) => *existing = replacement.clone(),
+                None => routes.push(replacement.clone()),
+            }
+        }
+    });
+
+    Ok(Json(replacements))
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RouteQuery {
+    method: Option<String>,
+    path: Option<String>,
+}
+
+/// Removes the routes matching the query; an empty query removes every route.
+async fn delete_routes(
+    State(server): State<MockServer>,
+    Query(query): Query<RouteQuery>,
+) -> Result<Json<Vec<ApplicationRequirements>>, AdminError> {
+    let matches = |route: &ApplicationRequirements| {
+        query
+            .method
+            .as_ref()
+            .is_none_or(|method| method.eq_ignore_ascii_case(&route.http_method.to_string()))
+            && query.path.as_ref().is_none_or(|path| *path == route.path)
+    };
+
+    let removed = server.update_routes(|routes| {
+        let (removed, kept) = routes.drain(..).partition(|route| matches(route));
+        *routes = kept;
+        removed
+    });
+
+    if removed.is_empty() {
+        return Err(AdminError::RouteNotFound);
+    }
+
+    Ok(Json(removed))
+}
+
+/// Reads routes in the `.http` format, or as JSON (one route or a list) when
+/// the request says so. Response files are looked up under `file_root`.
+fn parse_routes(
+    headers: &HeaderMap,
+    body: String,
+    file_root: &Path,
+) -> Result<Vec<ApplicationRequirements>, AdminError> {
+    let is_json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+
+    let mut routes: Vec<ApplicationRequirements> = if is_json {
+        match serde_json::from_str::<serde_json::Value>(&body)? {
+            list @ serde_json::Value::Array(_) => serde_json::from_value(list)?,
+            route => vec![serde_json::from_value(route)?],
+        }
+    } else {
+        tokenizer::parse_http(body)?
+    };
+
+    if let Some(route) = routes
+        .iter()
+        .find(|route| route.path.starts_with(ADMIN_PREFIX))
+    {
+        return Err(AdminError::ReservedPath(route.path.clone()));
+    }
+    // JSON routes haven't been through the `.http` parser's checks.
+    for route in &routes {
+        PathPattern::parse(&route.path)?;
+        if StatusCode::from_u16(route.http_response_status).is_err() {
+            return Err(AdminError::InvalidStatus(route.http_response_status));
+        }
+    }
+    // Anyone who can reach the admin API could otherwise read any file the server can.
+    for route in &mut routes {
+        if let Some(file) = &route.http_response_path {
+            let inside = Path::new(file)
+                .components()
+                .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+            if !inside {
+                return Err(AdminError::FileOutsideRoot(file.clone()));
+            }
+            route.resolve_response_path(file_root);
+        }
+    }
+
+    Ok(routes)
+}
+
+fn same_route(a: &ApplicationRequirements, b: &ApplicationRequirements) -> bool {
+    a.http_method == b.http_method && a.path == b.path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app_requirements::HttpMethods;
+
+    fn json_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn parse_routes_accepts_json_and_http_definitions() {
+        let from_json = parse_routes(
+            &json_headers(),
+            r#"{"method": "POST", "path": "/api/user", "status": 201, "body": {"id": 123}}"#
+                .to_string(),
+            Path::new(""),
+        )
+        .unwrap();
+        let from_http = parse_routes(
+            &HeaderMap::new(),
+            "POST /api/user 201\n\n{\n  \"id\": 123\n}".to_string(),
+            Path::new(""),
+        )
+        .unwrap();
+
+        assert_eq!(from_json, from_http);
+        assert_eq!(from_json[0].http_method, HttpMethods::POST);
+        assert_eq!(
+            from_json[0].http_response_body.as_deref(),
+            Some(r#"{"id":123}"#)
+        );
+    }
+
+    #[test]
+    fn parse_routes_rejects_reserved_paths() {
+        let result = parse_routes(
+            &json_headers(),
+            r#"[{"method": "GET", "path": "/__testroute/routes", "status": 200}]"#.to_string(),
+            Path::new(""),
+        );
+        assert!(matches!(result, Err(AdminError::ReservedPath(_))));
+    }
+
+    #[test]
+    fn parse_routes_rejects_invalid_paths() {
+        let result = parse_routes(
+            &json_headers(),
+            r#"{"method": "GET", "path": "/files/*path/raw", "status": 200}"#.to_string(),
+            Path::new(""),
+        );
+        assert!(matches!(result, Err(AdminError::InvalidPath(_))));
+    }
+
+    #[test]
+    fn parse_routes_keeps_files_inside_the_import_directory() {
+        let root = Path::new("fixtures");
+        let routes = parse_routes(
+            &json_headers(),
+            r#"{"method": "GET", "path": "/avatar", "status": 200, "file": "img/avatar.png"}"#
+                .to_string(),
+            root,
+        )
+        .unwrap();
+        assert_eq!(
+            routes[0].http_response_path.as_deref(),
+            Some(root.join("img/avatar.png").to_str().unwrap())
+        );
+
+        for file in ["/etc/passwd", "../secrets.json", "img/../../secrets.json"] {
+            let result = parse_routes(
+                &json_headers(),
+                format!(r#"{{"method": "GET", "path": "/leak", "status": 200, "file": "{file}"}}"#),
+                root,
+            );
+            assert!(
+                matches!(result, Err(AdminError::FileOutsideRoot(_))),
+                "{file}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_routes_rejects_invalid_statuses() {
+        for status in [42, 1000] {
+            let result = parse_routes(
+                &json_headers(),
+                format!(r#"{{"method": "GET", "path": "/foo", "status": {status}}}"#),
+                Path::new(""),
+            );
+            assert!(
+                matches!(result, Err(AdminError::InvalidStatus(_))),
+                "{status}"
+            );
+        }
+    }
+}
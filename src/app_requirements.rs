@@ -1,35 +1,170 @@
 use core::fmt;
-use std::{str::FromStr, thread::sleep, time::Duration};
+use std::{collections::BTreeMap, path::Path, str::FromStr};
 
-use inquire::{required, Select, Text};
-use strum::VariantNames;
-use strum_macros::{EnumString, VariantNames};
+use inquire::{required, validator::Validation, Select, Text};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
 
-use crate::{prompts, Args};
+use crate::{
+    cors::CorsSettings,
+    delay::{Delay, Jitter},
+    event_stream::EventStream,
+    fault::Fault,
+    prompts,
+    throttle::Bandwidth,
+    websocket::WebSocketScript,
+    Args,
+};
 
-#[derive(Debug, EnumString, VariantNames, Clone, Eq, PartialEq)]
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+#[error("Invalid HTTP method, expected e.g. `GET`, `ANY` or an uppercase custom verb like `PROPFIND`: {0}")]
+pub struct InvalidMethod(pub String);
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
 pub enum HttpMethods {
+    #[default]
     GET,
     POST,
     PUT,
     PATCH,
     DELETE,
+    /// Answered by the GET route of the path when it has no HEAD route, without the body.
+    HEAD,
+    OPTIONS,
+    TRACE,
+    /// Every method the path has no route for.
+    ANY,
+    /// A WebSocket endpoint, upgraded from a GET and played by its `WebSocketScript`.
+    WS,
+    /// Any other verb, e.g. WebDAV's `PROPFIND`.
+    Custom(String),
+}
+
+impl HttpMethods {
+    /// The methods offered when prompting for a route, custom verbs are given with `--method`.
+    pub const KNOWN: [&'static str; 10] = [
+        "GET", "POST", "PUT", "PATCH", "DELETE", "HEAD", "OPTIONS", "TRACE", "ANY", "WS",
+    ];
+}
+
+impl FromStr for HttpMethods {
+    type Err = InvalidMethod;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(match value {
+            "GET" => HttpMethods::GET,
+            "POST" => HttpMethods::POST,
+            "PUT" => HttpMethods::PUT,
+            "PATCH" => HttpMethods::PATCH,
+            "DELETE" => HttpMethods::DELETE,
+            "HEAD" => HttpMethods::HEAD,
+            "OPTIONS" => HttpMethods::OPTIONS,
+            "TRACE" => HttpMethods::TRACE,
+            "ANY" => HttpMethods::ANY,
+            "WS" => HttpMethods::WS,
+            // Lowercase verbs are valid HTTP but far more likely to be typos of `get`.
+            custom
+                if !custom.is_empty()
+                    && custom.bytes().all(|byte| {
+                        byte.is_ascii_uppercase()
+                            || byte.is_ascii_digit()
+                            || byte == b'-'
+                            || byte == b'_'
+                    }) =>
+            {
+                HttpMethods::Custom(custom.to_string())
+            }
+            _ => return Err(InvalidMethod(value.to_string())),
+        })
+    }
 }
 
 impl fmt::Display for HttpMethods {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            HttpMethods::Custom(method) => write!(f, "{method}"),
+            method => write!(f, "{:?}", method),
+        }
+    }
+}
+
+impl Serialize for HttpMethods {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for HttpMethods {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq)]
+/// A mock route. Serialized with the short field names the admin API accepts:
+/// `{"method": "GET", "path": "/foo", "status": 200, "body": {"foo": true}}`.
+#[derive(Debug, Default, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct ApplicationRequirements {
     pub path: String,
+    #[serde(rename = "method")]
     pub http_method: HttpMethods,
+    #[serde(rename = "status")]
     pub http_response_status: u16,
+    #[serde(rename = "body", default, deserialize_with = "deserialize_body")]
     pub http_response_body: Option<String>,
+    #[serde(rename = "file", default)]
     pub http_response_path: Option<String>,
-    pub delay: Option<usize>,
+    #[serde(
+        rename = "headers",
+        default,
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub http_response_headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub delay: Option<Delay>,
+    /// Throughput the body is streamed at, overriding `--throttle`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub throttle: Option<Bandwidth>,
+    /// Wait between sending the headers and the first body byte, overriding `--first-byte`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_byte: Option<Delay>,
+    /// Breaks the connection instead of answering normally.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fault: Option<Fault>,
+    /// Only clients whose certificate subject contains this, e.g. `CN=alice`, get the response.
+    #[serde(
+        rename = "client_cert",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub client_subject: Option<String>,
+    /// Cross-origin settings on top of the global ones.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cors: Option<CorsSettings>,
+    /// Scripted events sent one by one instead of the body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<EventStream>,
+    /// What a `WS` route sends and answers once connected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub websocket: Option<WebSocketScript>,
+    /// Wins over the other routes matching the same request when higher, `0` when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+}
+
+/// Accepts the body either as a plain string or as inline JSON.
+fn deserialize_body<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    Ok(
+        match Option::<serde_json::Value>::deserialize(deserializer)? {
+            Some(serde_json::Value::String(body)) => Some(body),
+            Some(value) => Some(value.to_string()),
+            None => None,
+        },
+    )
 }
 
 impl ApplicationRequirements {
@@ -45,7 +180,7 @@ impl ApplicationRequirements {
             HttpMethods::from_str(
                 Select::new(
                     "What HTTP method should listen to?",
-                    HttpMethods::VARIANTS.to_vec(),
+                    HttpMethods::KNOWN.to_vec(),
                 )
                 .prompt()
                 .unwrap(),
@@ -71,10 +206,17 @@ impl ApplicationRequirements {
                 .unwrap()
         });
 
-        let delay = args.delay.unwrap_or_else(|| {
-            Text::new("There's any delay that you want on the route? (in seconds)")
+        let delay = args.delay.or_else(|| {
+            let delay = Text::new("There's any delay that you want on the route? (e.g. 250ms, 1.5s or uniform(100ms, 800ms))")
+                .with_validator(|value: &str| {
+                    Ok(match value.parse::<Delay>() {
+                        Err(err) if !value.trim().is_empty() => Validation::Invalid(err.into()),
+                        _ => Validation::Valid,
+                    })
+                })
                 .prompt()
-                .unwrap()
+                .unwrap();
+            delay.parse().ok()
         });
 
         Self {
@@ -82,26 +224,41 @@ impl ApplicationRequirements {
             http_method,
             http_response_status,
             http_response_body: None,
-            http_response_path: if http_response.len() > 0 {
+            http_response_path: if !http_response.is_empty() {
                 Some(http_response)
             } else {
                 None
             },
-            delay: if delay.len() > 0 {
-                Some(delay.parse::<usize>().unwrap())
-            } else {
-                None
-            },
+            delay,
+            fault: args.fault,
+            ..Self::default()
         }
     }
 
-    pub fn try_sleep(&self) -> Option<()> {
-        if let Some(delay) = self.delay {
-            sleep(Duration::from_secs(delay.try_into().unwrap()));
+    /// Makes a relative response file path relative to `base`, the directory
+    /// of the `.http` file it was declared in, rather than the working directory.
+    pub fn resolve_response_path(&mut self, base: &Path) {
+        if let Some(path) = &self.http_response_path {
+            if Path::new(path).is_relative() {
+                self.http_response_path = Some(base.join(path).to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    /// Identifies the route in logs and in the server state, e.g. `GET /api/user/:id`.
+    pub fn route_key(&self) -> String {
+        format!("{} {}", self.http_method, self.path)
+    }
+
+    pub fn priority(&self) -> i32 {
+        self.priority.unwrap_or_default()
+    }
 
-            Some(())
-        } else {
-            None
+    /// Waits out the route's delay, drawn from `jitter`, without holding up
+    /// other requests.
+    pub async fn try_sleep(&self, jitter: &Jitter) {
+        if let Some(delay) = &self.delay {
+            tokio::time::sleep(jitter.sample(delay)).await;
         }
     }
 }
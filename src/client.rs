@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    http::{header, uri::PathAndQuery, HeaderValue, Request, Response, Uri},
+};
+use hyper::body::Incoming;
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::{TokioExecutor, TokioIo},
+};
+use tokio::net::TcpStream;
+use tokio_rustls::{
+    rustls::{pki_types::ServerName, ClientConfig},
+    TlsConnector,
+};
+
+/// Plain HTTP client for talking to upstreams and to a running testroute.
+pub type HttpClient = Client<HttpConnector, Body>;
+
+pub fn new() -> HttpClient {
+    Client::builder(TokioExecutor::new()).build_http()
+}
+
+/// Sends `request` to its absolute `http` or `https` URI over a connection of
+/// its own. `https` needs `tls`, see `tls::client_config`.
+pub async fn send_once(
+    mut request: Request<Body>,
+    tls: Option<Arc<ClientConfig>>,
+) -> Result<Response<Incoming>, String> {
+    let uri = request.uri().clone();
+    let host = uri.host().ok_or_else(|| format!("Missing host in {uri}"))?;
+    let https = uri.scheme_str() == Some("https");
+    let port = uri.port_u16().unwrap_or(if https { 443 } else { 80 });
+
+    // Sent in origin form, as the server expects from a client.
+    let path = uri
+        .path_and_query()
+        .cloned()
+        .unwrap_or_else(|| PathAndQuery::from_static("/"));
+    *request.uri_mut() = Uri::from(path);
+    if let Some(authority) = uri.authority() {
+        let host = HeaderValue::from_str(authority.as_str()).map_err(|err| err.to_string())?;
+        request.headers_mut().entry(header::HOST).or_insert(host);
+    }
+
+    let stream = TcpStream::connect((host.trim_matches(['[', ']']), port))
+        .await
+        .map_err(|err| err.to_string())?;
+    if !https {
+        return exchange(TokioIo::new(stream), request).await;
+    }
+
+    let tls = tls.ok_or_else(|| format!("No TLS settings to connect to {uri}"))?;
+    let name = ServerName::try_from(host.trim_matches(['[', ']']).to_string())
+        .map_err(|err| err.to_string())?;
+    let stream = TlsConnector::from(tls)
+        .connect(name, stream)
+        .await
+        .map_err(|err| err.to_string())?;
+    exchange(TokioIo::new(stream), request).await
+}
+
+async fn exchange<I>(io: I, request: Request<Body>) -> Result<Response<Incoming>, String>
+where
+    I: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(io)
+        .await
+        .map_err(|err| err.to_string())?;
+    tokio::spawn(connection);
+    sender
+        .send_request(request)
+        .await
+        .map_err(|err| err.to_string())
+}
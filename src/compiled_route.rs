@@ -0,0 +1,137 @@
+use axum::{
+    body::{Body, Bytes},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Response, StatusCode},
+};
+
+use crate::{
+    app_requirements::ApplicationRequirements, journal::MatchedRoute, server::ServerConfig,
+};
+
+/// A route with what it answers worked out once, when the routes change,
+/// rather than on every request.
+#[derive(Debug)]
+pub struct CompiledRoute {
+    pub app: ApplicationRequirements,
+    /// `route_key()`, counting the hits.
+    pub key: String,
+    pub status: StatusCode,
+    /// The route's headers, after the `Content-Type` inferred for its file or stream.
+    pub headers: HeaderMap,
+    /// The inline body, placeholders included.
+    pub body: Bytes,
+    /// Whether the inline body has placeholders to fill for each request.
+    pub templated: bool,
+    /// Whether the response is the same for every request, save CORS headers.
+    pub fixed: bool,
+}
+
+impl CompiledRoute {
+    pub fn new(app: ApplicationRequirements, config: &ServerConfig) -> Self {
+        let mut headers = HeaderMap::new();
+        // Unless the route sets one, e.g. `image/png` for an `avatar.png` file.
+        let has_content_type = app
+            .http_response_headers
+            .keys()
+            .any(|name| name.eq_ignore_ascii_case("content-type"));
+        let content_type = match (&app.stream, &app.http_response_path) {
+            (Some(stream), _) => Some(stream.format.content_type()),
+            (None, Some(path)) => mime_guess::from_path(path).first_raw(),
+            (None, None) => None,
+        };
+        if let Some(content_type) = content_type.filter(|_| !has_content_type) {
+            headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+        }
+        for (name, value) in &app.http_response_headers {
+            match (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+                (Ok(name), Ok(value)) => {
+                    headers.append(name, value);
+                }
+                _ => eprintln!(
+                    "Skipping invalid header of {}: {name}: {value}",
+                    app.route_key()
+                ),
+            }
+        }
+
+        let body = app
+            .http_response_body
+            .clone()
+            .map(Bytes::from)
+            .unwrap_or_default();
+        let templated = body.windows(2).any(|window| window == b"{{");
+        let fixed = !templated
+            && app.http_response_path.is_none()
+            && app.stream.is_none()
+            && app.fault.is_none()
+            && app.delay.is_none()
+            && app.throttle.or(config.throttle).is_none()
+            && app.first_byte.is_none()
+            && config.first_byte.is_none();
+
+        Self {
+            key: app.route_key(),
+            // Building the routes mustn't bring the server down, e.g. from the admin API.
+            status: StatusCode::from_u16(app.http_response_status).unwrap_or_else(|_| {
+                eprintln!(
+                    "Invalid status of {}, answering 500 instead",
+                    app.route_key()
+                );
+                StatusCode::INTERNAL_SERVER_ERROR
+            }),
+            headers,
+            body,
+            templated,
+            fixed,
+            app,
+        }
+    }
+
+    /// The response of a `fixed` route, sharing the precompiled body.
+    pub fn fixed_response(&self) -> Response<Body> {
+        let mut response = Response::new(Body::from(self.body.clone()));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+            .extensions_mut()
+            .insert(MatchedRoute(self.app.path.clone()));
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_plain_bodies_are_fixed() {
+        let route = |body: &str| ApplicationRequirements {
+            path: "/users".to_string(),
+            http_response_status: 200,
+            http_response_body: Some(body.to_string()),
+            http_response_headers: [("X-Mock".to_string(), "yes".to_string())].into(),
+            ..ApplicationRequirements::default()
+        };
+        let config = ServerConfig::default();
+
+        let compiled = CompiledRoute::new(route("[]"), &config);
+        assert!(compiled.fixed);
+        assert_eq!(compiled.fixed_response().headers()["x-mock"], "yes");
+        assert!(!CompiledRoute::new(route("{{id}}"), &config).fixed);
+
+        let throttled = ServerConfig {
+            throttle: Some("16kb".parse().unwrap()),
+            ..ServerConfig::default()
+        };
+        assert!(!CompiledRoute::new(route("[]"), &throttled).fixed);
+
+        let file = ApplicationRequirements {
+            http_response_status: 200,
+            http_response_path: Some("avatar.png".to_string()),
+            ..ApplicationRequirements::default()
+        };
+        assert_eq!(
+            CompiledRoute::new(file, &config).headers[header::CONTENT_TYPE],
+            "image/png"
+        );
+    }
+}
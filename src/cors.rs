@@ -0,0 +1,206 @@
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, Response, StatusCode},
+};
+use serde::{Deserialize, Serialize};
+
+/// Cross-origin settings, given for the whole server with the `--cors*` flags
+/// or for a single route with the `Cors*=` options. A route only overrides
+/// the fields it sets.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CorsSettings {
+    /// Origins allowed to call, any when empty or when it holds `*`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub origins: Vec<String>,
+    /// Methods preflights allow, the ones mocked on the path when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub methods: Vec<String>,
+    /// Request headers preflights allow, whatever the browser asks for when empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credentials: Option<bool>,
+    /// Seconds browsers may cache a preflight answer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_age: Option<u64>,
+}
+
+impl CorsSettings {
+    /// The route's settings on top of the global ones, `None` when neither is set.
+    pub fn resolve(
+        global: Option<&CorsSettings>,
+        route: Option<&CorsSettings>,
+    ) -> Option<CorsSettings> {
+        let (global, route) = match (global, route) {
+            (None, None) => return None,
+            (Some(settings), None) | (None, Some(settings)) => return Some(settings.clone()),
+            (Some(global), Some(route)) => (global, route),
+        };
+
+        let pick = |route: &Vec<String>, global: &Vec<String>| {
+            if route.is_empty() {
+                global.clone()
+            } else {
+                route.clone()
+            }
+        };
+
+        Some(CorsSettings {
+            origins: pick(&route.origins, &global.origins),
+            methods: pick(&route.methods, &global.methods),
+            headers: pick(&route.headers, &global.headers),
+            credentials: route.credentials.or(global.credentials),
+            max_age: route.max_age.or(global.max_age),
+        })
+    }
+
+    fn allows_any_origin(&self) -> bool {
+        self.origins.is_empty() || self.origins.iter().any(|origin| origin == "*")
+    }
+
+    fn allows_credentials(&self) -> bool {
+        self.credentials.unwrap_or(false)
+    }
+
+    /// Headers every response to a cross-origin request gets, `None` when the
+    /// origin isn't allowed.
+    pub fn response_headers(&self, origin: &HeaderValue) -> Option<HeaderMap> {
+        let mut headers = HeaderMap::new();
+
+        // Browsers refuse `*` on credentialed requests, so the origin is echoed back.
+        if self.allows_any_origin() && !self.allows_credentials() {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_ORIGIN,
+                HeaderValue::from_static("*"),
+            );
+        } else if self.allows_any_origin()
+            || self
+                .origins
+                .iter()
+                .any(|allowed| allowed.as_bytes() == origin.as_bytes())
+        {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin.clone());
+            headers.insert(header::VARY, HeaderValue::from_static("Origin"));
+        } else {
+            return None;
+        }
+
+        if self.allows_credentials() {
+            headers.insert(
+                header::ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+
+        Some(headers)
+    }
+
+    /// Answers an OPTIONS request on a path serving `mocked_methods`.
+    pub fn preflight(&self, request: &HeaderMap, mocked_methods: &[String]) -> Response<Body> {
+        let methods = if self.methods.is_empty() {
+            mocked_methods.join(", ")
+        } else {
+            self.methods.join(", ")
+        };
+
+        let Some(origin) = request.get(header::ORIGIN) else {
+            // Not a preflight, just someone asking what the path supports.
+            return Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .header(header::ALLOW, methods)
+                .body(Body::empty())
+                .unwrap();
+        };
+
+        let Some(headers) = self.response_headers(origin) else {
+            return Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body(Body::from(format!(
+                    "Origin {} is not allowed",
+                    String::from_utf8_lossy(origin.as_bytes())
+                )))
+                .unwrap();
+        };
+
+        let mut response = Response::builder().status(StatusCode::NO_CONTENT);
+        for (name, value) in &headers {
+            response = response.header(name, value);
+        }
+        response = response.header(header::ACCESS_CONTROL_ALLOW_METHODS, methods);
+
+        if !self.headers.is_empty() {
+            response = response.header(
+                header::ACCESS_CONTROL_ALLOW_HEADERS,
+                self.headers.join(", "),
+            );
+        } else if let Some(requested) = request.get(header::ACCESS_CONTROL_REQUEST_HEADERS) {
+            response = response
+                .header(header::ACCESS_CONTROL_ALLOW_HEADERS, requested)
+                .header(header::VARY, "Access-Control-Request-Headers");
+        }
+        if let Some(max_age) = self.max_age {
+            response = response.header(header::ACCESS_CONTROL_MAX_AGE, max_age);
+        }
+
+        response.body(Body::empty()).unwrap()
+    }
+}
+
+/// Splits a `GET, POST` style list.
+pub fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn route_settings_override_global_ones_field_by_field() {
+        let global = CorsSettings {
+            origins: vec!["http://localhost:3000".to_string()],
+            max_age: Some(600),
+            ..CorsSettings::default()
+        };
+        let route = CorsSettings {
+            credentials: Some(true),
+            ..CorsSettings::default()
+        };
+
+        let resolved = CorsSettings::resolve(Some(&global), Some(&route)).unwrap();
+        assert_eq!(resolved.origins, global.origins);
+        assert_eq!(resolved.credentials, Some(true));
+        assert_eq!(resolved.max_age, Some(600));
+        assert_eq!(CorsSettings::resolve(None, None), None);
+    }
+
+    #[test]
+    fn response_headers_echo_the_origin_when_needed() {
+        let origin = HeaderValue::from_static("http://localhost:3000");
+
+        let any = CorsSettings::default().response_headers(&origin).unwrap();
+        assert_eq!(any[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+
+        let credentialed = CorsSettings {
+            credentials: Some(true),
+            ..CorsSettings::default()
+        };
+        let headers = credentialed.response_headers(&origin).unwrap();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://localhost:3000"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+
+        let restricted = CorsSettings {
+            origins: vec!["http://localhost:5173".to_string()],
+            ..CorsSettings::default()
+        };
+        assert!(restricted.response_headers(&origin).is_none());
+    }
+}
@@ -0,0 +1,371 @@
+use std::{fmt, str::FromStr, sync::Mutex, time::Duration};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+#[error(
+    "Invalid delay, expected e.g. `250ms`, `1.5s`, `uniform(100ms, 800ms)`, `normal(300ms, 50ms)` \
+     or `percentiles(p50=100ms, p99=2s)`: {0}"
+)]
+pub struct InvalidDelay(pub String);
+
+/// How long a route waits before answering, drawn again for every request.
+///
+/// Durations are written as `250ms`, `1.5s`, `1m`, or a bare number of seconds.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Delay {
+    Fixed(Duration),
+    /// Anywhere between the two bounds, e.g. `uniform(100ms, 800ms)`.
+    Uniform(Duration, Duration),
+    /// Around a mean, never below zero, e.g. `normal(300ms, 50ms)`.
+    Normal {
+        mean: Duration,
+        stddev: Duration,
+    },
+    /// A latency profile, e.g. `percentiles(p50=100ms, p90=400ms, p99=2s)`.
+    /// Percentiles are kept in hundredths (p99.9 is 9990) and ascend; delays
+    /// between two of them are interpolated, and the first and last one
+    /// bound the rest.
+    Percentiles(Vec<(u32, Duration)>),
+}
+
+impl Delay {
+    /// Draws the delay for one request.
+    pub fn sample(&self, rng: &mut impl Rng) -> Duration {
+        match self {
+            Delay::Fixed(delay) => *delay,
+            Delay::Uniform(min, max) => *min + (*max - *min).mul_f64(rng.gen::<f64>()),
+            Delay::Normal { mean, stddev } => {
+                // Box-Muller transform; 1 - gen() keeps ln away from zero.
+                let radius = (-2.0 * (1.0 - rng.gen::<f64>()).ln()).sqrt();
+                let angle = 2.0 * std::f64::consts::PI * rng.gen::<f64>();
+                let seconds = mean.as_secs_f64() + radius * angle.cos() * stddev.as_secs_f64();
+                Duration::from_secs_f64(seconds.max(0.0))
+            }
+            Delay::Percentiles(points) => interpolate(points, rng.gen::<f64>() * 10_000.0),
+        }
+    }
+
+    /// Whether every draw is zero, e.g. `0s` or `uniform(0s, 0s)`.
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Delay::Fixed(delay) => delay.is_zero(),
+            Delay::Uniform(_, max) => max.is_zero(),
+            Delay::Normal { mean, stddev } => mean.is_zero() && stddev.is_zero(),
+            Delay::Percentiles(points) => points.iter().all(|(_, delay)| delay.is_zero()),
+        }
+    }
+}
+
+fn interpolate(points: &[(u32, Duration)], quantile: f64) -> Duration {
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return Duration::ZERO;
+    };
+    if quantile <= f64::from(first.0) {
+        return first.1;
+    }
+
+    points
+        .windows(2)
+        .find(|pair| quantile <= f64::from(pair[1].0))
+        .map(|pair| {
+            let ((low, from), (high, to)) = (pair[0], pair[1]);
+            let position = (quantile - f64::from(low)) / f64::from(high - low);
+            Duration::from_secs_f64(
+                from.as_secs_f64() + (to.as_secs_f64() - from.as_secs_f64()) * position,
+            )
+        })
+        .unwrap_or(last.1)
+}
+
+/// Where delay distributions draw from, seeded with `--delay-seed` to replay
+/// the same sequence of delays.
+#[derive(Debug)]
+pub struct Jitter(Mutex<StdRng>);
+
+impl Jitter {
+    pub fn new(seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        Self(Mutex::new(rng))
+    }
+
+    pub fn sample(&self, delay: &Delay) -> Duration {
+        let mut rng = self
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        delay.sample(&mut *rng)
+    }
+}
+
+impl Default for Jitter {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+/// Parses `250ms`, `1.5s`, `1m` or a bare number of seconds.
+fn parse_duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: f64 = amount.parse().ok()?;
+
+    let seconds = match unit.trim() {
+        "ms" => amount / 1000.0,
+        "" | "s" => amount,
+        "m" => amount * 60.0,
+        _ => return None,
+    };
+
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Parses `p99.9` into hundredths of a percentile.
+fn parse_percentile(value: &str) -> Option<u32> {
+    let percentile: f64 = value.trim().strip_prefix('p')?.parse().ok()?;
+    (0.0..=100.0)
+        .contains(&percentile)
+        .then(|| (percentile * 100.0).round() as u32)
+}
+
+impl FromStr for Delay {
+    type Err = InvalidDelay;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidDelay(value.to_string());
+        let trimmed = value.trim();
+
+        let Some((function, arguments)) = trimmed
+            .strip_suffix(')')
+            .and_then(|call| call.split_once('('))
+        else {
+            return parse_duration(trimmed)
+                .map(Delay::Fixed)
+                .ok_or_else(invalid);
+        };
+        let arguments: Vec<&str> = arguments.split(',').map(str::trim).collect();
+
+        let delay = match (function.trim(), arguments.as_slice()) {
+            ("uniform", [min, max]) => {
+                let (min, max) = (parse_duration(min), parse_duration(max));
+                match min.zip(max) {
+                    Some((min, max)) if min <= max => Delay::Uniform(min, max),
+                    _ => return Err(invalid()),
+                }
+            }
+            ("normal", [mean, stddev]) => Delay::Normal {
+                mean: parse_duration(mean).ok_or_else(invalid)?,
+                stddev: parse_duration(stddev).ok_or_else(invalid)?,
+            },
+            ("percentiles", points) => {
+                let points = points
+                    .iter()
+                    .map(|point| {
+                        let (percentile, delay) = point.split_once('=')?;
+                        Some((parse_percentile(percentile)?, parse_duration(delay)?))
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(invalid)?;
+                let ascending = points
+                    .windows(2)
+                    .all(|pair| pair[0].0 < pair[1].0 && pair[0].1 <= pair[1].1);
+                if points.is_empty() || !ascending {
+                    return Err(invalid());
+                }
+                Delay::Percentiles(points)
+            }
+            _ => return Err(invalid()),
+        };
+
+        Ok(delay)
+    }
+}
+
+struct DisplayDuration(Duration);
+
+impl fmt::Display for DisplayDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let duration = self.0;
+        if duration.subsec_nanos() == 0 {
+            write!(f, "{}s", duration.as_secs())
+        } else if duration.subsec_nanos().is_multiple_of(1_000_000) {
+            write!(f, "{}ms", duration.as_millis())
+        } else {
+            write!(f, "{}s", duration.as_secs_f64())
+        }
+    }
+}
+
+impl fmt::Display for Delay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Delay::Fixed(delay) => write!(f, "{}", DisplayDuration(*delay)),
+            Delay::Uniform(min, max) => write!(
+                f,
+                "uniform({}, {})",
+                DisplayDuration(*min),
+                DisplayDuration(*max)
+            ),
+            Delay::Normal { mean, stddev } => {
+                write!(
+                    f,
+                    "normal({}, {})",
+                    DisplayDuration(*mean),
+                    DisplayDuration(*stddev)
+                )
+            }
+            Delay::Percentiles(points) => {
+                let points: Vec<String> = points
+                    .iter()
+                    .map(|(percentile, delay)| {
+                        format!(
+                            "p{}={}",
+                            f64::from(*percentile) / 100.0,
+                            DisplayDuration(*delay)
+                        )
+                    })
+                    .collect();
+                write!(f, "percentiles({})", points.join(", "))
+            }
+        }
+    }
+}
+
+impl Serialize for Delay {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Accepts `"250ms"` as well as the plain number of seconds older state files hold.
+impl<'de> Deserialize<'de> for Delay {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::String(value) => value.parse().map_err(serde::de::Error::custom),
+            serde_json::Value::Number(seconds) => seconds
+                .as_f64()
+                .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                .map(Delay::Fixed)
+                .ok_or_else(|| serde::de::Error::custom(InvalidDelay(seconds.to_string()))),
+            other => Err(serde::de::Error::custom(InvalidDelay(other.to_string()))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn delays_accept_units_and_bare_seconds() {
+        assert_eq!("250ms".parse(), Ok(Delay::Fixed(ms(250))));
+        assert_eq!("1.5s".parse(), Ok(Delay::Fixed(ms(1500))));
+        assert_eq!("2".parse(), Ok(Delay::Fixed(Duration::from_secs(2))));
+        assert_eq!("1m".parse(), Ok(Delay::Fixed(Duration::from_secs(60))));
+
+        for invalid in ["", "fast", "-1s", "10 parsecs", "1.2.3s"] {
+            assert!(
+                invalid.parse::<Delay>().is_err(),
+                "{invalid} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn distributions_parse_and_validate() {
+        assert_eq!(
+            "uniform(100ms, 800ms)".parse(),
+            Ok(Delay::Uniform(ms(100), ms(800)))
+        );
+        assert_eq!(
+            "normal(300ms, 50ms)".parse(),
+            Ok(Delay::Normal {
+                mean: ms(300),
+                stddev: ms(50)
+            })
+        );
+        assert_eq!(
+            "percentiles(p50=100ms, p99.9=2s)".parse(),
+            Ok(Delay::Percentiles(vec![(5000, ms(100)), (9990, ms(2000))]))
+        );
+
+        for invalid in [
+            "uniform(800ms, 100ms)",
+            "uniform(100ms)",
+            "normal(300ms, often)",
+            "percentiles(p90=1s, p50=100ms)",
+            "percentiles(p101=1s)",
+            "percentiles()",
+            "gamma(1s, 2s)",
+        ] {
+            assert!(
+                invalid.parse::<Delay>().is_err(),
+                "{invalid} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn delays_round_trip_through_text_and_json() {
+        for text in [
+            "250ms",
+            "2s",
+            "uniform(100ms, 1500ms)",
+            "normal(300ms, 50ms)",
+            "percentiles(p50=100ms, p99.9=2s)",
+        ] {
+            assert_eq!(text.parse::<Delay>().unwrap().to_string(), text);
+        }
+
+        let delay: Delay = serde_json::from_str("3").unwrap();
+        assert_eq!(delay, Delay::Fixed(Duration::from_secs(3)));
+        assert_eq!(serde_json::to_string(&delay).unwrap(), r#""3s""#);
+    }
+
+    #[test]
+    fn samples_stay_in_range_and_replay_with_a_seed() {
+        let uniform = Delay::Uniform(ms(100), ms(800));
+        let profile: Delay = "percentiles(p50=100ms, p90=400ms, p99=2s)".parse().unwrap();
+        let normal = Delay::Normal {
+            mean: ms(10),
+            stddev: ms(100),
+        };
+
+        let jitter = Jitter::new(Some(7));
+        for _ in 0..1000 {
+            let delay = jitter.sample(&uniform);
+            assert!((ms(100)..=ms(800)).contains(&delay));
+            let delay = jitter.sample(&profile);
+            assert!((ms(100)..=ms(2000)).contains(&delay));
+            jitter.sample(&normal);
+        }
+
+        let draws = |seed| {
+            let jitter = Jitter::new(Some(seed));
+            (0..10).map(|_| jitter.sample(&uniform)).collect::<Vec<_>>()
+        };
+        assert_eq!(draws(42), draws(42));
+        assert_ne!(draws(42), draws(43));
+    }
+
+    #[test]
+    fn percentile_profiles_interpolate_between_points() {
+        let profile = [(5000, ms(100)), (9000, ms(500))];
+        assert_eq!(interpolate(&profile, 2500.0), ms(100));
+        assert_eq!(interpolate(&profile, 7000.0), ms(300));
+        assert_eq!(interpolate(&profile, 9900.0), ms(500));
+    }
+}
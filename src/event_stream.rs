@@ -0,0 +1,341 @@
+use std::{convert::Infallible, fmt, str::FromStr, sync::Arc};
+
+use axum::body::{Body, Bytes};
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use strum_macros::{Display, EnumString};
+use thiserror::Error;
+
+use crate::{
+    delay::{Delay, Jitter},
+    fault::ConnectionControl,
+};
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+#[error(
+    "Invalid event, expected e.g. `Event=delay=500ms; id=1; event=tick; data={{\"n\": 1}}`: {0}"
+)]
+pub struct InvalidEvent(pub String);
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+#[error("Invalid repeat, expected a number of plays or `forever`: {0}")]
+pub struct InvalidRepeat(pub String);
+
+/// How the events of a stream are written on the wire.
+#[derive(
+    Debug, Clone, Copy, Default, Eq, PartialEq, Display, EnumString, Serialize, Deserialize,
+)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamFormat {
+    /// Server-Sent Events, `text/event-stream`.
+    #[default]
+    Sse,
+    /// Newline-delimited JSON, one event per line.
+    Ndjson,
+}
+
+impl StreamFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            StreamFormat::Sse => "text/event-stream",
+            StreamFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    fn encode(self, event: &StreamEvent) -> Bytes {
+        match self {
+            StreamFormat::Sse => {
+                let mut frame = String::new();
+                if let Some(id) = &event.id {
+                    frame.push_str(&format!("id: {id}\n"));
+                }
+                if let Some(name) = &event.event {
+                    frame.push_str(&format!("event: {name}\n"));
+                }
+                for line in event.data.lines() {
+                    frame.push_str(&format!("data: {line}\n"));
+                }
+                frame.push('\n');
+                Bytes::from(frame)
+            }
+            // Pretty JSON would spill over several lines.
+            StreamFormat::Ndjson => match serde_json::from_str::<serde_json::Value>(&event.data) {
+                Ok(json) => Bytes::from(format!("{json}\n")),
+                Err(_) => Bytes::from(format!("{}\n", event.data.replace('\n', " "))),
+            },
+        }
+    }
+}
+
+/// How many times the events of a stream are played.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Repeat {
+    Times(u32),
+    /// Until the client disconnects.
+    Forever,
+}
+
+impl Repeat {
+    fn plays(self, play: u32) -> bool {
+        match self {
+            Repeat::Times(times) => play < times,
+            Repeat::Forever => true,
+        }
+    }
+}
+
+impl FromStr for Repeat {
+    type Err = InvalidRepeat;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim() {
+            "forever" => Ok(Repeat::Forever),
+            times => times
+                .parse()
+                .ok()
+                .filter(|times| *times > 0)
+                .map(Repeat::Times)
+                .ok_or_else(|| InvalidRepeat(value.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Repeat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Repeat::Times(times) => write!(f, "{times}"),
+            Repeat::Forever => write!(f, "forever"),
+        }
+    }
+}
+
+impl Serialize for Repeat {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Repeat::Times(times) => serializer.serialize_u32(*times),
+            Repeat::Forever => serializer.serialize_str("forever"),
+        }
+    }
+}
+
+/// Accepts `3` as well as `"3"` and `"forever"`.
+impl<'de> Deserialize<'de> for Repeat {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::String(value) => value.parse().map_err(serde::de::Error::custom),
+            other => other.to_string().parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// One scripted event.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct StreamEvent {
+    /// Wait before sending the event.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delay: Option<Delay>,
+    /// The SSE `id:` field, which browsers send back as `Last-Event-ID` when reconnecting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The SSE `event:` field, the name `addEventListener` listens to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<String>,
+    #[serde(deserialize_with = "deserialize_data")]
+    pub data: String,
+}
+
+/// Accepts the data either as a plain string or as inline JSON.
+pub fn deserialize_data<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    Ok(match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(data) => data,
+        value => value.to_string(),
+    })
+}
+
+const FIELDS: [&str; 4] = ["delay=", "id=", "event=", "data="];
+
+/// Parses the value of an `Event=` option: `key=value` fields separated by
+/// `;`, the data last as it may hold anything. A value without fields is all data.
+impl FromStr for StreamEvent {
+    type Err = InvalidEvent;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidEvent(value.to_string());
+        let mut event = StreamEvent::default();
+        let mut rest = value.trim();
+
+        loop {
+            let Some(field) = FIELDS.iter().find(|field| rest.starts_with(*field)) else {
+                event.data = rest.to_string();
+                break;
+            };
+            if *field == "data=" {
+                event.data = rest[field.len()..].trim().to_string();
+                break;
+            }
+
+            let (current, next) = rest.split_once(';').unwrap_or((rest, ""));
+            let field_value = current[field.len()..].trim().to_string();
+            match *field {
+                "delay=" => event.delay = Some(field_value.parse().map_err(|_| invalid())?),
+                "id=" => event.id = Some(field_value),
+                _ => event.event = Some(field_value),
+            }
+            rest = next.trim();
+        }
+
+        Ok(event)
+    }
+}
+
+/// Writes the event back as the value of an `Event=` option.
+impl fmt::Display for StreamEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut fields = Vec::new();
+        if let Some(delay) = &self.delay {
+            fields.push(format!("delay={delay}"));
+        }
+        if let Some(id) = &self.id {
+            fields.push(format!("id={id}"));
+        }
+        if let Some(event) = &self.event {
+            fields.push(format!("event={event}"));
+        }
+
+        if fields.is_empty() && !FIELDS.iter().any(|field| self.data.starts_with(field)) {
+            return write!(f, "{}", self.data);
+        }
+        fields.push(format!("data={}", self.data));
+        write!(f, "{}", fields.join("; "))
+    }
+}
+
+/// A scripted stream of events a route answers with instead of a single body.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct EventStream {
+    #[serde(default)]
+    pub format: StreamFormat,
+    #[serde(default)]
+    pub events: Vec<StreamEvent>,
+    /// Plays the events once when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repeat: Option<Repeat>,
+}
+
+impl EventStream {
+    /// Sends the events one by one, each after its delay drawn from `jitter`,
+    /// ending early when the server shuts down so endless streams don't hold it up.
+    pub fn body(&self, jitter: Arc<Jitter>, control: Option<ConnectionControl>) -> Body {
+        if self.events.is_empty() {
+            return Body::empty();
+        }
+
+        let events = self.events.clone();
+        let repeat = self.repeat.unwrap_or(Repeat::Times(1));
+        let format = self.format;
+        let script = (0..)
+            .take_while(move |play| repeat.plays(*play))
+            .flat_map(move |_| events.clone());
+
+        let frames = stream::iter(script).then(move |event| {
+            let jitter = jitter.clone();
+            async move {
+                if let Some(delay) = &event.delay {
+                    tokio::time::sleep(jitter.sample(delay)).await;
+                }
+                Ok::<_, Infallible>(format.encode(&event))
+            }
+        });
+
+        let shutdown = async move {
+            match control {
+                Some(control) => control.closing().await,
+                None => std::future::pending().await,
+            }
+        };
+        Body::from_stream(frames.take_until(shutdown))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    #[test]
+    fn events_parse_fields_then_data() {
+        let event: StreamEvent = r#"delay=500ms; id=1; event=tick; data={"n": 1; "m": 2}"#
+            .parse()
+            .unwrap();
+        assert_eq!(
+            event,
+            StreamEvent {
+                delay: Some(Delay::Fixed(Duration::from_millis(500))),
+                id: Some("1".to_string()),
+                event: Some("tick".to_string()),
+                data: r#"{"n": 1; "m": 2}"#.to_string(),
+            }
+        );
+        assert_eq!(event.to_string().parse::<StreamEvent>().unwrap(), event);
+
+        let plain: StreamEvent = r#"{"n": 1}"#.parse().unwrap();
+        assert_eq!(plain.data, r#"{"n": 1}"#);
+        assert_eq!(plain.to_string(), r#"{"n": 1}"#);
+
+        assert!("delay=soon; data=x".parse::<StreamEvent>().is_err());
+    }
+
+    #[test]
+    fn events_are_framed_for_each_format() {
+        let event = StreamEvent {
+            id: Some("7".to_string()),
+            event: Some("update".to_string()),
+            data: "{\n  \"n\": 1\n}".to_string(),
+            ..StreamEvent::default()
+        };
+
+        assert_eq!(
+            StreamFormat::Sse.encode(&event),
+            "id: 7\nevent: update\ndata: {\ndata:   \"n\": 1\ndata: }\n\n"
+        );
+        assert_eq!(StreamFormat::Ndjson.encode(&event), "{\"n\":1}\n");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn streams_replay_their_events_with_delays() {
+        let start = Instant::now();
+        let stream = EventStream {
+            format: StreamFormat::Ndjson,
+            events: vec!["1".parse().unwrap(), "delay=100ms; data=2".parse().unwrap()],
+            repeat: Some(Repeat::Times(3)),
+        };
+
+        let body = to_bytes(stream.body(Arc::default(), None), usize::MAX)
+            .await
+            .unwrap();
+
+        assert_eq!(body, "1\n2\n1\n2\n1\n2\n");
+        assert_eq!(start.elapsed(), Duration::from_millis(300));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn endless_streams_end_when_the_server_shuts_down() {
+        let (shutdown, signal) = tokio::sync::watch::channel(());
+        let stream = EventStream {
+            format: StreamFormat::Ndjson,
+            events: vec!["delay=1s; data=tick".parse().unwrap()],
+            repeat: Some(Repeat::Forever),
+        };
+        let body = stream.body(Arc::default(), Some(ConnectionControl::new(signal)));
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(2500)).await;
+            shutdown.send(()).unwrap();
+        });
+
+        assert_eq!(to_bytes(body, usize::MAX).await.unwrap(), "tick\ntick\n");
+    }
+}
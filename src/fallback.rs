@@ -0,0 +1,246 @@
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+use axum::{
+    body::Body,
+    http::{header, request::Parts, HeaderName, HeaderValue, Response, StatusCode},
+    response::IntoResponse,
+};
+use serde_json::json;
+
+use crate::{app_requirements::ApplicationRequirements, template};
+
+/// How many routes an unmatched request is told about.
+const CLOSEST_ROUTES: usize = 3;
+
+/// What requests matching no route get instead of an empty 404, set with a
+/// `FALLBACK` block in the `.http` file or the `--fallback*` flags.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Fallback {
+    pub status: u16,
+    pub headers: BTreeMap<String, String>,
+    /// A template, see `render`.
+    pub body: Option<String>,
+    /// A template file, used when there's no `body`.
+    pub file: Option<String>,
+    /// Without a body or file, answers with JSON describing the request and
+    /// the routes closest to it.
+    pub suggest_routes: bool,
+}
+
+impl Default for Fallback {
+    fn default() -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND.as_u16(),
+            headers: BTreeMap::new(),
+            body: None,
+            file: None,
+            suggest_routes: false,
+        }
+    }
+}
+
+impl Fallback {
+    /// Makes a relative template file path relative to `base`, like route
+    /// files, and checks the file can be read.
+    pub fn resolve_file(&mut self, base: &Path) -> io::Result<()> {
+        if let Some(file) = &self.file {
+            if Path::new(file).is_relative() {
+                self.file = Some(base.join(file).to_string_lossy().into_owned());
+            }
+        }
+
+        match &self.file {
+            Some(file) => fs::metadata(file).map(|_| ()),
+            None => Ok(()),
+        }
+    }
+
+    /// Answers `request`, which none of `routes` matched.
+    pub async fn respond(
+        &self,
+        request: &Parts,
+        routes: &[ApplicationRequirements],
+    ) -> Response<Body> {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::NOT_FOUND);
+        let mut response = Response::builder().status(status);
+
+        let template = match (&self.body, &self.file) {
+            (Some(body), _) => Some(body.clone()),
+            (None, Some(file)) => match tokio::fs::read_to_string(file).await {
+                Ok(template) => Some(template),
+                Err(err) => {
+                    let message = format!("Failed to read the fallback file {file}: {err}");
+                    eprintln!("{message}");
+                    return (StatusCode::INTERNAL_SERVER_ERROR, message).into_response();
+                }
+            },
+            (None, None) => None,
+        };
+        let body = match template {
+            Some(template) => render(&template, request),
+            None if self.suggest_routes => {
+                if !self
+                    .headers
+                    .keys()
+                    .any(|name| name.eq_ignore_ascii_case("content-type"))
+                {
+                    response = response.header(header::CONTENT_TYPE, "application/json");
+                }
+                suggestions(request, routes).to_string()
+            }
+            None => String::new(),
+        };
+
+        // Checked when the fallback is read, skipped rather than failing every response.
+        for (name, value) in &self.headers {
+            if let (Ok(name), Ok(value)) =
+                (HeaderName::try_from(name), HeaderValue::try_from(value))
+            {
+                response = response.header(name, value);
+            }
+        }
+
+        response.body(Body::from(body)).unwrap()
+    }
+}
+
+/// Fills `{{method}}`, `{{path}}`, `{{query}}` and `{{uri}}` in with the request's.
+fn render(template: &str, request: &Parts) -> String {
+    template::render(template, |name| template::request_value(name, request))
+}
+
+fn suggestions(request: &Parts, routes: &[ApplicationRequirements]) -> serde_json::Value {
+    let path = request.uri.path();
+    let mut ranked: Vec<(usize, &ApplicationRequirements)> = routes
+        .iter()
+        .map(|route| {
+            // Same distance, same method first.
+            let other_method = route.http_method.to_string() != request.method.as_str();
+            (
+                distance(path, &route.path) * 2 + usize::from(other_method),
+                route,
+            )
+        })
+        .collect();
+    ranked.sort_by_key(|(distance, _)| *distance);
+
+    json!({
+        "error": format!("No route matches {} {}", request.method, path),
+        "request": {
+            "method": request.method.as_str(),
+            "path": path,
+            "query": request.uri.query(),
+        },
+        "closest_routes": ranked
+            .iter()
+            .take(CLOSEST_ROUTES)
+            .map(|(_, route)| route.route_key())
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// How far `path` is from a route pattern: the edit distance between them,
+/// once the pattern's `:params` are filled in with the request's segments.
+fn distance(path: &str, pattern: &str) -> usize {
+    let segments: Vec<&str> = path.split('/').collect();
+    let filled: Vec<&str> = pattern
+        .split('/')
+        .enumerate()
+        .map(|(index, segment)| match segments.get(index) {
+            Some(actual) if segment.starts_with(':') => actual,
+            _ => segment,
+        })
+        .collect();
+
+    levenshtein(path, &filled.join("/"))
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Request;
+
+    fn route(method: &str, path: &str) -> ApplicationRequirements {
+        ApplicationRequirements {
+            path: path.to_string(),
+            http_method: method.parse().unwrap(),
+            http_response_status: 200,
+            ..ApplicationRequirements::default()
+        }
+    }
+
+    fn request(method: &str, uri: &str) -> Parts {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(())
+            .unwrap()
+            .into_parts()
+            .0
+    }
+
+    #[test]
+    fn templates_get_the_request() {
+        let fallback = Fallback {
+            body: Some(
+                r#"{"error": "{{method}} {{path}} not mocked", "query": "{{query}}"}"#.to_string(),
+            ),
+            ..Fallback::default()
+        };
+
+        assert_eq!(
+            render(
+                fallback.body.as_deref().unwrap(),
+                &request("DELETE", "/api/users?force=true")
+            ),
+            r#"{"error": "DELETE /api/users not mocked", "query": "force=true"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn unreadable_files_are_reported() {
+        let mut fallback = Fallback {
+            file: Some("testroute-missing-fallback.html".to_string()),
+            ..Fallback::default()
+        };
+        assert!(fallback.resolve_file(&std::env::temp_dir()).is_err());
+
+        let response = fallback.respond(&request("GET", "/missing"), &[]).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn suggestions_rank_the_closest_routes_first() {
+        let routes = [
+            route("GET", "/api/orders"),
+            route("POST", "/api/users"),
+            route("GET", "/api/users/:id"),
+            route("GET", "/api/users"),
+            route("GET", "/health"),
+        ];
+
+        let suggested = suggestions(&request("GET", "/api/user/42"), &routes);
+
+        assert_eq!(suggested["request"]["path"], "/api/user/42");
+        assert_eq!(
+            suggested["closest_routes"],
+            json!(["GET /api/users/:id", "GET /api/users", "POST /api/users"])
+        );
+    }
+}
@@ -0,0 +1,314 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum::{
+    body::{Body, Bytes},
+    http::{StatusCode, Version},
+};
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString, VariantNames};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+    sync::watch,
+};
+
+/// How long a truncated body waits after its first half before breaking off.
+const TRUNCATE_AFTER: Duration = Duration::from_millis(50);
+
+/// A broken server behavior a route can answer with instead of a response.
+#[derive(
+    Debug, Clone, Copy, Eq, PartialEq, Display, EnumString, VariantNames, Serialize, Deserialize,
+)]
+#[strum(serialize_all = "kebab-case")]
+#[serde(rename_all = "kebab-case")]
+pub enum Fault {
+    /// Resets the TCP connection (RST) without answering.
+    Reset,
+    /// Closes the connection cleanly (FIN) without answering.
+    Close,
+    /// Announces the full `Content-Length` but sends half of the body.
+    Truncate,
+    /// Sends a chunked body whose chunk sizes aren't hex, then closes. HTTP/2
+    /// has no chunked encoding, so the connection is reset instead.
+    MalformedChunks,
+    /// Never answers, until the client gives up or the server shuts down.
+    Hang,
+}
+
+/// What the connection should do next, set by the route handler.
+#[derive(Debug, Default)]
+enum Sabotage {
+    #[default]
+    None,
+    Close,
+    Reset,
+    /// Writes these bytes in place of the response, then closes.
+    Raw(Bytes),
+}
+
+/// Lets a handler break the connection its request came in on. Attached to
+/// every request by `serve::serve`.
+#[derive(Debug, Clone)]
+pub struct ConnectionControl {
+    sabotage: Arc<Mutex<Sabotage>>,
+    shutdown: watch::Receiver<()>,
+}
+
+impl ConnectionControl {
+    pub fn new(shutdown: watch::Receiver<()>) -> Self {
+        Self {
+            sabotage: Arc::default(),
+            shutdown,
+        }
+    }
+
+    fn sabotage(&self, sabotage: Sabotage) {
+        *self
+            .sabotage
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = sabotage;
+    }
+
+    /// Resolves when the server shuts down.
+    pub async fn closing(&self) {
+        let _ = self.shutdown.clone().changed().await;
+    }
+}
+
+/// Gets at the TCP socket under a connection, plain or TLS.
+pub trait TcpSocket {
+    fn tcp(&self) -> &TcpStream;
+}
+
+impl TcpSocket for TcpStream {
+    fn tcp(&self) -> &TcpStream {
+        self
+    }
+}
+
+impl TcpSocket for tokio_rustls::server::TlsStream<TcpStream> {
+    fn tcp(&self) -> &TcpStream {
+        self.get_ref().0
+    }
+}
+
+/// A connection that fails on purpose once its `ConnectionControl` says so.
+pub struct FaultyIo<I> {
+    inner: I,
+    control: ConnectionControl,
+    raw_written: usize,
+}
+
+impl<I: TcpSocket> FaultyIo<I> {
+    pub fn new(inner: I, control: ConnectionControl) -> Self {
+        Self {
+            inner,
+            control,
+            raw_written: 0,
+        }
+    }
+
+    /// The error to fail with, if the connection was sabotaged.
+    fn broken(&self) -> Option<io::Error> {
+        match &*self
+            .control
+            .sabotage
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+        {
+            Sabotage::None | Sabotage::Raw(_) => None,
+            Sabotage::Close => Some(io::ErrorKind::ConnectionAborted.into()),
+            Sabotage::Reset => {
+                // A zero linger makes closing the socket send RST instead of FIN.
+                let _ = socket2::SockRef::from(self.inner.tcp()).set_linger(Some(Duration::ZERO));
+                Some(io::ErrorKind::ConnectionReset.into())
+            }
+        }
+    }
+}
+
+impl<I: AsyncRead + TcpSocket + Unpin> AsyncRead for FaultyIo<I> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if let Some(err) = self.broken() {
+            return Poll::Ready(Err(err));
+        }
+
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<I: AsyncWrite + TcpSocket + Unpin> AsyncWrite for FaultyIo<I> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if let Some(err) = self.broken() {
+            return Poll::Ready(Err(err));
+        }
+
+        let raw = match &*self
+            .control
+            .sabotage
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+        {
+            Sabotage::Raw(raw) => Some(raw.clone()),
+            _ => None,
+        };
+        let Some(raw) = raw else {
+            return Pin::new(&mut self.inner).poll_write(cx, buf);
+        };
+
+        // What hyper wanted to write is dropped in favor of the raw bytes.
+        while self.raw_written < raw.len() {
+            let written = self.raw_written;
+            match Pin::new(&mut self.inner).poll_write(cx, &raw[written..]) {
+                Poll::Ready(Ok(n)) => self.raw_written += n,
+                other => return other,
+            }
+        }
+        self.control.sabotage(Sabotage::Close);
+        Poll::Ready(Err(io::ErrorKind::ConnectionAborted.into()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// What the route handler answers with when its route has a fault.
+pub enum Injected {
+    /// Serve the response with this body and `Content-Length`.
+    Truncated(Body, usize),
+    /// The connection is broken, whatever is answered won't reach the client.
+    Broken,
+}
+
+/// Sets off `fault` on the request's connection.
+pub async fn inject(
+    fault: Fault,
+    control: Option<&ConnectionControl>,
+    version: Version,
+    status: StatusCode,
+    body: Bytes,
+) -> Injected {
+    let sabotage = |sabotage| {
+        if let Some(control) = control {
+            control.sabotage(sabotage);
+        }
+    };
+
+    match fault {
+        Fault::Reset => sabotage(Sabotage::Reset),
+        Fault::Close => sabotage(Sabotage::Close),
+        Fault::Truncate => {
+            let length = body.len();
+            return Injected::Truncated(truncated_body(body), length);
+        }
+        Fault::MalformedChunks if version >= Version::HTTP_2 => sabotage(Sabotage::Reset),
+        Fault::MalformedChunks => sabotage(Sabotage::Raw(malformed_chunks(status))),
+        Fault::Hang => match control {
+            Some(control) => {
+                control.closing().await;
+                control.sabotage(Sabotage::Reset);
+            }
+            None => std::future::pending().await,
+        },
+    }
+
+    Injected::Broken
+}
+
+/// Half of `body`, then an error that makes the server drop the connection.
+fn truncated_body(mut body: Bytes) -> Body {
+    let half = body.split_to(body.len() / 2);
+    let chunks = stream::once(async { Ok(half) }).chain(stream::once(async {
+        // Gives the server time to flush the headers and the first half.
+        tokio::time::sleep(TRUNCATE_AFTER).await;
+        Err(io::Error::from(io::ErrorKind::UnexpectedEof))
+    }));
+    Body::from_stream(chunks)
+}
+
+fn malformed_chunks(status: StatusCode) -> Bytes {
+    Bytes::from(format!(
+        "HTTP/1.1 {status}\r\nTransfer-Encoding: chunked\r\nContent-Type: text/plain\r\n\r\n\
+         5\r\nhello\r\nnot-a-size\r\n world\r\n"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    #[test]
+    fn faults_are_written_in_kebab_case() {
+        assert_eq!("malformed-chunks".parse(), Ok(Fault::MalformedChunks));
+        assert_eq!(Fault::Reset.to_string(), "reset");
+        assert!("explode".parse::<Fault>().is_err());
+    }
+
+    #[tokio::test]
+    async fn truncated_bodies_fail_halfway() {
+        let body = truncated_body(Bytes::from_static(b"0123456789"));
+        assert!(to_bytes(body, usize::MAX).await.is_err());
+    }
+
+    /// Connects a client to a sabotaged server-side connection and returns
+    /// what the client reads.
+    async fn read_from_sabotaged(sabotage: Sabotage) -> io::Result<Vec<u8>> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (server, _) = listener.accept().await.unwrap();
+
+        let (_shutdown_tx, shutdown_rx) = watch::channel(());
+        let control = ConnectionControl::new(shutdown_rx);
+        let mut server = FaultyIo::new(server, control.clone());
+        control.sabotage(sabotage);
+
+        assert!(server.write_all(b"HTTP/1.1 200 OK\r\n\r\n").await.is_err());
+        drop(server);
+
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.map(|_| received)
+    }
+
+    #[tokio::test]
+    async fn sabotaged_connections_reset_close_or_send_raw_bytes() {
+        let reset = read_from_sabotaged(Sabotage::Reset).await;
+        assert_eq!(reset.unwrap_err().kind(), io::ErrorKind::ConnectionReset);
+
+        assert_eq!(read_from_sabotaged(Sabotage::Close).await.unwrap(), b"");
+
+        let raw = malformed_chunks(StatusCode::OK);
+        assert_eq!(
+            read_from_sabotaged(Sabotage::Raw(raw.clone()))
+                .await
+                .unwrap(),
+            raw
+        );
+    }
+}
@@ -0,0 +1,313 @@
+use std::{
+    collections::HashMap,
+    io,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    sync::RwLock,
+    time::SystemTime,
+};
+
+use axum::body::Bytes;
+use futures_util::{stream::BoxStream, StreamExt};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+use tokio_util::io::ReaderStream;
+
+/// Files up to this size are read at once, so their `{{name}}` placeholders
+/// can be filled. Bigger ones are streamed from disk as they are.
+const TEMPLATE_LIMIT: u64 = 1024 * 1024;
+
+/// The bytes `start..=end` of a body, as asked with `Range: bytes=start-end`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+/// What a `Range` header asks of a body of a given length.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RangeRequest {
+    /// No range, or one we don't serve (another unit, several ranges, a
+    /// malformed one): the whole body is sent.
+    Whole,
+    Partial(ByteRange),
+    /// The range starts past the end, answered with a 416.
+    Unsatisfiable,
+}
+
+/// Reads `bytes=0-499`, `bytes=500-` and `bytes=-500` (the last 500 bytes).
+pub fn parse_range(header: &str, length: u64) -> RangeRequest {
+    let Some(spec) = header.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Whole;
+    };
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return RangeRequest::Whole;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Whole;
+    }
+
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", "") => return RangeRequest::Whole,
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => return RangeRequest::Unsatisfiable,
+            Ok(suffix) => (length.saturating_sub(suffix), length.saturating_sub(1)),
+            Err(_) => return RangeRequest::Whole,
+        },
+        (start, "") => match start.parse::<u64>() {
+            Ok(start) => (start, length.saturating_sub(1)),
+            Err(_) => return RangeRequest::Whole,
+        },
+        (start, end) => match (start.parse::<u64>(), end.parse::<u64>()) {
+            (Ok(start), Ok(end)) if start <= end => (start, end.min(length.saturating_sub(1))),
+            _ => return RangeRequest::Whole,
+        },
+    };
+
+    if start >= length {
+        return RangeRequest::Unsatisfiable;
+    }
+    RangeRequest::Partial(ByteRange { start, end })
+}
+
+/// The body of a `File=` route.
+pub enum FileBody {
+    Loaded(Bytes),
+    /// Too big to be held in memory, sent straight from disk.
+    OnDisk {
+        file: File,
+        range: ByteRange,
+    },
+}
+
+impl FileBody {
+    /// Reads small files, from `cache` when given, filling the placeholders of
+    /// text ones with `render`, and leaves big ones on disk.
+    pub async fn open(
+        path: &Path,
+        cache: Option<&FileCache>,
+        render: impl FnOnce(&str) -> String,
+    ) -> io::Result<Self> {
+        let contents = match cache {
+            Some(cache) => cache.load(path).await?,
+            None => read_small(File::open(path).await?).await?,
+        };
+
+        match contents {
+            Ok(contents) => Ok(FileBody::Loaded(fill_placeholders(contents, render))),
+            Err(file) => {
+                let length = file.metadata().await?.len();
+                let range = ByteRange {
+                    start: 0,
+                    end: length - 1,
+                };
+                Ok(FileBody::OnDisk { file, range })
+            }
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        match self {
+            FileBody::Loaded(bytes) => bytes.len() as u64,
+            FileBody::OnDisk { range, .. } => range.len(),
+        }
+    }
+
+    pub fn is_on_disk(&self) -> bool {
+        matches!(self, FileBody::OnDisk { .. })
+    }
+
+    /// Keeps `range` of the body only, `range` being within `0..len()`.
+    pub fn slice(&mut self, range: ByteRange) {
+        match self {
+            FileBody::Loaded(bytes) => {
+                *bytes = bytes.slice(range.start as usize..=range.end as usize)
+            }
+            FileBody::OnDisk { range: kept, .. } => {
+                *kept = ByteRange {
+                    start: kept.start + range.start,
+                    end: kept.start + range.end,
+                };
+            }
+        }
+    }
+
+    pub async fn into_bytes(self) -> io::Result<Bytes> {
+        match self {
+            FileBody::Loaded(bytes) => Ok(bytes),
+            FileBody::OnDisk { .. } => {
+                let mut contents = Vec::new();
+                let mut chunks = self.into_stream().await?;
+                while let Some(chunk) = chunks.next().await {
+                    contents.extend_from_slice(&chunk?);
+                }
+                Ok(Bytes::from(contents))
+            }
+        }
+    }
+
+    /// The body in chunks, read from disk as they are sent.
+    pub async fn into_stream(self) -> io::Result<BoxStream<'static, io::Result<Bytes>>> {
+        match self {
+            FileBody::Loaded(bytes) => Ok(futures_util::stream::once(async { Ok(bytes) }).boxed()),
+            FileBody::OnDisk { mut file, range } => {
+                file.seek(SeekFrom::Start(range.start)).await?;
+                Ok(ReaderStream::new(file.take(range.len())).boxed())
+            }
+        }
+    }
+}
+
+/// The contents of `file`, or the file itself when it's too big to be read at once.
+async fn read_small(mut file: File) -> io::Result<Result<Bytes, File>> {
+    let length = file.metadata().await?.len();
+    if length > TEMPLATE_LIMIT {
+        return Ok(Err(file));
+    }
+
+    let mut contents = Vec::with_capacity(length as usize);
+    file.read_to_end(&mut contents).await?;
+    Ok(Ok(Bytes::from(contents)))
+}
+
+/// Only text with placeholders is copied, the rest is sent as read.
+fn fill_placeholders(contents: Bytes, render: impl FnOnce(&str) -> String) -> Bytes {
+    match std::str::from_utf8(&contents) {
+        Ok(text) if text.contains("{{") => Bytes::from(render(text)),
+        _ => contents,
+    }
+}
+
+/// Keeps the small response files in memory, see `--cache-files`. A file is
+/// read again once its size or modification time changes.
+#[derive(Debug, Default)]
+pub struct FileCache {
+    files: RwLock<HashMap<PathBuf, CachedFile>>,
+}
+
+#[derive(Debug)]
+struct CachedFile {
+    length: u64,
+    modified: SystemTime,
+    contents: Bytes,
+}
+
+impl FileCache {
+    async fn load(&self, path: &Path) -> io::Result<Result<Bytes, File>> {
+        let metadata = tokio::fs::metadata(path).await?;
+        let modified = metadata.modified()?;
+
+        let cached = {
+            let files = self
+                .files
+                .read()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            files
+                .get(path)
+                .filter(|cached| cached.length == metadata.len() && cached.modified == modified)
+                .map(|cached| cached.contents.clone())
+        };
+        if let Some(contents) = cached {
+            return Ok(Ok(contents));
+        }
+
+        let contents = read_small(File::open(path).await?).await?;
+        if let Ok(contents) = &contents {
+            let cached = CachedFile {
+                length: contents.len() as u64,
+                modified,
+                contents: contents.clone(),
+            };
+            self.files
+                .write()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .insert(path.to_path_buf(), cached);
+        }
+        Ok(contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_are_parsed_against_the_body_length() {
+        let partial = |start, end| RangeRequest::Partial(ByteRange { start, end });
+
+        assert_eq!(parse_range("bytes=0-99", 1000), partial(0, 99));
+        assert_eq!(parse_range("bytes=900-", 1000), partial(900, 999));
+        assert_eq!(parse_range("bytes=-100", 1000), partial(900, 999));
+        assert_eq!(parse_range("bytes=-5000", 1000), partial(0, 999));
+        assert_eq!(parse_range("bytes=990-5000", 1000), partial(990, 999));
+        assert_eq!(
+            parse_range("bytes=1000-", 1000),
+            RangeRequest::Unsatisfiable
+        );
+        assert_eq!(parse_range("bytes=-0", 1000), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=0-", 0), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=5-1", 1000), RangeRequest::Whole);
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), RangeRequest::Whole);
+        assert_eq!(parse_range("items=0-1", 1000), RangeRequest::Whole);
+    }
+
+    #[tokio::test]
+    async fn big_files_are_streamed_from_disk() {
+        let path = std::env::temp_dir().join(format!("testroute-{}-big.bin", std::process::id()));
+        let contents: Vec<u8> = (0..TEMPLATE_LIMIT + 10)
+            .map(|index| (index % 251) as u8)
+            .collect();
+        std::fs::write(&path, &contents).unwrap();
+
+        let mut body = FileBody::open(&path, None, |_| unreachable!("big files aren't templated"))
+            .await
+            .unwrap();
+        assert!(body.is_on_disk());
+        body.slice(ByteRange {
+            start: 1000,
+            end: TEMPLATE_LIMIT,
+        });
+
+        assert_eq!(body.len(), TEMPLATE_LIMIT - 999);
+        assert_eq!(
+            body.into_bytes().await.unwrap(),
+            contents[1000..=TEMPLATE_LIMIT as usize]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn cached_files_are_read_again_once_changed() {
+        let path =
+            std::env::temp_dir().join(format!("testroute-{}-cached.json", std::process::id()));
+        let cache = FileCache::default();
+        let read = |cache| {
+            let path = path.clone();
+            async move {
+                match FileBody::open(&path, Some(cache), |text| text.replace("{{id}}", "7"))
+                    .await
+                    .unwrap()
+                {
+                    FileBody::Loaded(bytes) => bytes,
+                    FileBody::OnDisk { .. } => unreachable!("the file is small"),
+                }
+            }
+        };
+
+        std::fs::write(&path, r#"{"id": {{id}}}"#).unwrap();
+        assert_eq!(read(&cache).await, r#"{"id": 7}"#);
+        assert_eq!(read(&cache).await, r#"{"id": 7}"#);
+
+        std::fs::write(&path, r#"{"id": {{id}}, "name": "Foo"}"#).unwrap();
+        assert_eq!(read(&cache).await, r#"{"id": 7, "name": "Foo"}"#);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,299 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures_util::{stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::{admin::ADMIN_PREFIX, mtls::ClientIdentity, state::StateStore};
+
+/// How much of each request body is journaled. Past it, the body is passed
+/// on as it arrives and the entry is marked `body_truncated`.
+pub const BODY_LIMIT: usize = 64 * 1024;
+
+/// Route pattern that served a response, attached to it by the mock handler.
+#[derive(Debug, Clone)]
+pub struct MatchedRoute(pub String);
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: u64,
+    pub timestamp_ms: u64,
+    pub method: String,
+    pub uri: String,
+    /// Protocol the request came in with, e.g. `HTTP/2.0`.
+    #[serde(default)]
+    pub version: String,
+    pub path: String,
+    pub headers: BTreeMap<String, String>,
+    pub body: String,
+    /// Whether `body` holds only the first `BODY_LIMIT` bytes of the request body.
+    #[serde(default)]
+    pub body_truncated: bool,
+    pub matched_route: Option<String>,
+    pub status: u16,
+    /// Subject of the client certificate, when the connection presented one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_subject: Option<String>,
+}
+
+/// The last requests received by the server, oldest first.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Journal {
+    pub entries: VecDeque<JournalEntry>,
+    pub next_id: u64,
+}
+
+impl Journal {
+    pub fn push(&mut self, mut entry: JournalEntry, limit: usize) {
+        entry.id = self.next_id;
+        self.next_id += 1;
+
+        self.entries.push_back(entry);
+        while self.entries.len() > limit {
+            self.entries.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn query(&self, query: &JournalQuery) -> Vec<JournalEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| query.matches(entry))
+            .cloned()
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct JournalQuery {
+    pub method: Option<String>,
+    /// Either the requested path or the pattern of the route that matched it.
+    pub path: Option<String>,
+}
+
+impl JournalQuery {
+    pub fn matches(&self, entry: &JournalEntry) -> bool {
+        let method_matches = self
+            .method
+            .as_ref()
+            .is_none_or(|method| method.eq_ignore_ascii_case(&entry.method));
+
+        let path_matches = self
+            .path
+            .as_ref()
+            .is_none_or(|path| *path == entry.path || entry.matched_route.as_ref() == Some(path));
+
+        method_matches && path_matches
+    }
+}
+
+/// Middleware journaling every request outside the admin endpoints.
+pub async fn record(State(store): State<StateStore>, request: Request, next: Next) -> Response {
+    if request.uri().path().starts_with(ADMIN_PREFIX) {
+        return next.run(request).await;
+    }
+
+    let (parts, body) = request.into_parts();
+    let mut rest = body.into_data_stream();
+    let mut head = Vec::new();
+    let mut read = 0;
+    // One byte past the limit tells a truncated body from one of exactly `BODY_LIMIT` bytes.
+    while read <= BODY_LIMIT {
+        match rest.next().await {
+            Some(Ok(chunk)) => {
+                read += chunk.len();
+                head.push(chunk);
+            }
+            Some(Err(err)) => return (StatusCode::BAD_REQUEST, err.to_string()).into_response(),
+            None => break,
+        }
+    }
+    let body_truncated = read > BODY_LIMIT;
+    let journaled = first_bytes(&head, BODY_LIMIT);
+
+    let mut entry = JournalEntry {
+        id: 0,
+        timestamp_ms: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64),
+        method: parts.method.to_string(),
+        uri: parts.uri.to_string(),
+        version: format!("{:?}", parts.version),
+        path: parts.uri.path().to_string(),
+        headers: flatten_headers(&parts.headers),
+        body: String::from_utf8_lossy(&journaled).into_owned(),
+        body_truncated,
+        matched_route: None,
+        status: 0,
+        client_subject: parts
+            .extensions
+            .get::<ClientIdentity>()
+            .and_then(ClientIdentity::subject)
+            .map(|subject| subject.text.clone()),
+    };
+
+    let body = if body_truncated {
+        Body::from_stream(stream::iter(head.into_iter().map(Ok)).chain(rest))
+    } else {
+        Body::from(journaled)
+    };
+    let response = next.run(Request::from_parts(parts, body)).await;
+
+    entry.status = response.status().as_u16();
+    entry.matched_route = response
+        .extensions()
+        .get::<MatchedRoute>()
+        .map(|route| route.0.clone());
+    store.record_request(entry);
+
+    response
+}
+
+/// The first `limit` bytes of `chunks`, without copying when they are in the first one.
+fn first_bytes(chunks: &[Bytes], limit: usize) -> Bytes {
+    match chunks {
+        [] => Bytes::new(),
+        [chunk, ..] if chunk.len() >= limit || chunks.len() == 1 => {
+            chunk.slice(..chunk.len().min(limit))
+        }
+        _ => {
+            let mut bytes =
+                Vec::with_capacity(chunks.iter().map(Bytes::len).sum::<usize>().min(limit));
+            for chunk in chunks {
+                let taken = chunk.len().min(limit - bytes.len());
+                bytes.extend_from_slice(&chunk[..taken]);
+            }
+            Bytes::from(bytes)
+        }
+    }
+}
+
+fn flatten_headers(headers: &axum::http::HeaderMap) -> BTreeMap<String, String> {
+    let mut flattened: BTreeMap<String, String> = BTreeMap::new();
+
+    for (name, value) in headers {
+        let value = String::from_utf8_lossy(value.as_bytes());
+        flattened
+            .entry(name.to_string())
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(&value);
+            })
+            .or_insert_with(|| value.into_owned());
+    }
+
+    flattened
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{middleware, routing::post, Router};
+    use tower::ServiceExt;
+
+    fn entry(method: &str, path: &str, matched_route: Option<&str>) -> JournalEntry {
+        JournalEntry {
+            id: 0,
+            timestamp_ms: 0,
+            method: method.to_string(),
+            uri: path.to_string(),
+            version: "HTTP/1.1".to_string(),
+            path: path.to_string(),
+            headers: BTreeMap::new(),
+            body: String::new(),
+            body_truncated: false,
+            matched_route: matched_route.map(str::to_string),
+            status: 200,
+            client_subject: None,
+        }
+    }
+
+    #[test]
+    fn journal_drops_the_oldest_entries_past_the_limit() {
+        let mut journal = Journal::default();
+        for path in ["/a", "/b", "/c"] {
+            journal.push(entry("GET", path, None), 2);
+        }
+
+        let paths: Vec<_> = journal
+            .entries
+            .iter()
+            .map(|e| (e.id, e.path.as_str()))
+            .collect();
+        assert_eq!(paths, vec![(1, "/b"), (2, "/c")]);
+    }
+
+    #[tokio::test]
+    async fn big_bodies_are_journaled_in_part_and_passed_on_whole() {
+        let store = StateStore::open(Default::default(), None, false).unwrap();
+        let app = Router::new()
+            .route(
+                "/upload",
+                post(|body: Bytes| async move { body.len().to_string() }),
+            )
+            .layer(middleware::from_fn_with_state(store.clone(), record));
+
+        for size in [10, BODY_LIMIT, BODY_LIMIT + 1, 4 * BODY_LIMIT] {
+            let request = Request::post("/upload")
+                .body(Body::from(vec![b'x'; size]))
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            let received = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(received, size.to_string());
+
+            let entry = store.lock().journal.entries.pop_back().unwrap();
+            assert_eq!(entry.body.len(), size.min(BODY_LIMIT));
+            assert_eq!(entry.body_truncated, size > BODY_LIMIT);
+        }
+    }
+
+    #[test]
+    fn first_bytes_spans_chunks() {
+        let chunks = [
+            Bytes::from_static(b"abc"),
+            Bytes::from_static(b"def"),
+            Bytes::from_static(b"gh"),
+        ];
+        assert_eq!(first_bytes(&chunks, 4), "abcd");
+        assert_eq!(first_bytes(&chunks, 2), "ab");
+        assert_eq!(first_bytes(&chunks, 100), "abcdefgh");
+        assert_eq!(first_bytes(&[], 100), "");
+    }
+
+    #[test]
+    fn query_filters_by_method_and_path_or_route() {
+        let mut journal = Journal::default();
+        journal.push(entry("GET", "/api/user/1", Some("/api/user/:id")), 10);
+        journal.push(entry("POST", "/api/user/2", Some("/api/user/:id")), 10);
+        journal.push(entry("GET", "/missing", None), 10);
+
+        let query = JournalQuery {
+            method: Some("get".to_string()),
+            path: Some("/api/user/:id".to_string()),
+        };
+        let found = journal.query(&query);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].path, "/api/user/1");
+
+        let query = JournalQuery {
+            method: None,
+            path: Some("/missing".to_string()),
+        };
+        assert_eq!(journal.query(&query)[0].status, 200);
+        assert_eq!(journal.query(&JournalQuery::default()).len(), 3);
+    }
+}
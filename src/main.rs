@@ -1,24 +1,59 @@
+mod admin;
 mod app_requirements;
+mod client;
+mod compiled_route;
+mod cors;
+mod delay;
+mod event_stream;
+mod fallback;
+mod fault;
+mod file_body;
+mod journal;
+mod mtls;
 mod prompts;
+mod proxy;
+mod record;
+mod route_pattern;
+mod serve;
+mod server;
+mod state;
+mod template;
+mod throttle;
+mod tls;
 mod tokenizer;
+mod verify;
+mod websocket;
 
 use app_requirements::{ApplicationRequirements, HttpMethods};
-use axum::{
-    body::Body,
-    http::{Response, StatusCode},
-    response::IntoResponse,
-    routing::{delete, get, patch, post, put},
-    Router,
+use axum::http::StatusCode;
+use clap::{Parser, Subcommand};
+use cors::CorsSettings;
+use delay::{Delay, Jitter};
+use fallback::Fallback;
+use fault::Fault;
+use proxy::Proxy;
+use record::Recorder;
+use serve::HttpVersions;
+use server::{match_order, MockServer, ServerConfig};
+use state::{ServerState, StateStore};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
 };
-use clap::Parser;
-use std::fs;
+use throttle::Bandwidth;
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(short, long)]
     path: Option<String>,
 
+    /// HTTP method: GET, POST, PUT, PATCH, DELETE, HEAD, OPTIONS, TRACE, ANY, WS or a custom verb like PROPFIND
     #[arg(short, long)]
     method: Option<HttpMethods>,
 
@@ -28,76 +63,367 @@ struct Args {
     #[arg(short, long)]
     response: Option<String>,
 
+    /// Latency before answering, e.g. `250ms`, `1.5s` (bare numbers are seconds) or `uniform(100ms, 800ms)`
     #[arg(short, long)]
-    delay: Option<String>,
+    delay: Option<Delay>,
+
+    /// Break the connection instead of answering: reset, close, truncate, malformed-chunks or hang
+    #[arg(long)]
+    fault: Option<Fault>,
+
+    /// Seed for random delays, to get the same sequence of delays on every run
+    #[arg(long)]
+    delay_seed: Option<u64>,
+
+    /// Throughput response bodies are streamed at, e.g. `16kb` (bytes per second)
+    #[arg(long, value_name = "RATE")]
+    throttle: Option<Bandwidth>,
+
+    /// Wait between sending the headers and the first body byte, e.g. `500ms`
+    #[arg(long, value_name = "DELAY")]
+    first_byte: Option<Delay>,
 
     #[arg(short, long)]
     import: Option<String>,
+
+    /// Keep response files in memory, reading them again only when they change
+    #[arg(long)]
+    cache_files: bool,
+
+    /// Address to listen on, e.g. 0.0.0.0 to reach it from the LAN or ::1 for IPv6
+    #[arg(long, default_value = "127.0.0.1")]
+    host: String,
+
+    /// Port to listen on, 0 to let the OS pick a free one
+    #[arg(long, default_value_t = 9999)]
+    port: u16,
+
+    /// Serve over HTTPS with a generated self-signed certificate
+    #[arg(long)]
+    tls: bool,
+
+    /// PEM certificate chain to serve HTTPS with instead of a generated one
+    #[arg(long, value_name = "PEM", requires = "key")]
+    cert: Option<PathBuf>,
+
+    /// PEM private key matching --cert
+    #[arg(long, value_name = "PEM", requires = "cert")]
+    key: Option<PathBuf>,
+
+    /// Only speak HTTP/1.1, turning off HTTP/2 over TLS and h2c
+    #[arg(long)]
+    http1_only: bool,
+
+    /// Only serve mocks to clients with a certificate signed by one of these CAs (PEM, turns on HTTPS)
+    #[arg(long, value_name = "PEM")]
+    client_ca: Option<PathBuf>,
+
+    /// Status answered when the client certificate is missing, untrusted or doesn't match the route
+    #[arg(long, default_value_t = 403, requires = "client_ca", value_parser = clap::value_parser!(u16).range(100..1000))]
+    client_cert_status: u16,
+
+    /// JSON file the server state is restored from and saved to
+    #[arg(long)]
+    state_file: Option<PathBuf>,
+
+    /// Seconds between periodic state snapshots (0 to only save on shutdown)
+    #[arg(long, default_value_t = 30)]
+    state_interval: u64,
+
+    /// Start from the initial fixtures instead of the saved state
+    #[arg(long)]
+    reset_state: bool,
+
+    /// How many requests the journal keeps before dropping the oldest
+    #[arg(long, default_value_t = state::DEFAULT_JOURNAL_LIMIT)]
+    journal_limit: usize,
+
+    /// Answer cross-origin requests and preflights from any origin
+    #[arg(long)]
+    cors: bool,
+
+    /// Origin allowed to make cross-origin requests (repeatable, turns on CORS)
+    #[arg(long = "cors-origin", value_name = "ORIGIN")]
+    cors_origins: Vec<String>,
+
+    /// Method preflights allow, instead of the ones mocked on the path (repeatable, turns on CORS)
+    #[arg(long = "cors-method", value_name = "METHOD")]
+    cors_methods: Vec<String>,
+
+    /// Request header preflights allow, instead of the ones asked for (repeatable, turns on CORS)
+    #[arg(long = "cors-header", value_name = "NAME")]
+    cors_headers: Vec<String>,
+
+    /// Allow cookies and credentials on cross-origin requests (turns on CORS)
+    #[arg(long)]
+    cors_credentials: bool,
+
+    /// Seconds browsers may cache preflight answers (turns on CORS)
+    #[arg(long, value_name = "SECONDS")]
+    cors_max_age: Option<u64>,
+
+    /// Status answered to requests that match no route (default 404)
+    #[arg(long, value_name = "STATUS", value_parser = clap::value_parser!(u16).range(100..1000))]
+    fallback_status: Option<u16>,
+
+    /// Body answered to requests that match no route; {{method}}, {{path}}, {{query}} and {{uri}} are filled in
+    #[arg(long, value_name = "TEMPLATE")]
+    fallback_body: Option<String>,
+
+    /// Header answered to requests that match no route, as `Name: value` (repeatable)
+    #[arg(long = "fallback-header", value_name = "HEADER", value_parser = parse_header)]
+    fallback_headers: Vec<(String, String)>,
+
+    /// Answer requests that match no route with JSON listing the closest routes
+    #[arg(long)]
+    fallback_routes: bool,
+
+    /// Forward requests that match no mocked route to this server
+    #[arg(long, value_name = "URL")]
+    proxy_to: Option<String>,
+
+    /// Write a route for every new method and path answered by the proxy into this .http file
+    #[arg(long, value_name = "FILE", requires = "proxy_to")]
+    record: Option<PathBuf>,
+
+    /// Record numeric path segments as route params, e.g. /users/42 as /users/:id
+    #[arg(long, requires = "record")]
+    record_generalize_ids: bool,
+
+    /// Response header to record besides Content-Type and Content-Encoding (repeatable)
+    #[arg(long = "record-header", value_name = "NAME", requires = "record")]
+    record_headers: Vec<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check that a running testroute received the expected requests
+    Verify(verify::VerifyArgs),
 }
 
 #[tokio::main]
 async fn main() {
-    let args = Args::parse();
-    let requirements = if let Some(import) = args.import {
-        let file = fs::read_to_string(import).unwrap();
-        let tokens = tokenizer::tokens::parse(file);
-        tokenizer::ast::parse_requirements(&tokens).unwrap()
-    } else {
-        vec![ApplicationRequirements::get_from_user(args)]
-    };
+    let mut args = Args::parse();
 
-    let mut router = Router::new();
+    if let Some(Command::Verify(verify_args)) = args.command.take() {
+        std::process::exit(verify::run_cli(verify_args).await);
+    }
 
-    for requirement in &requirements {
-        let route_handler = || {
-            let app = requirement.clone();
-            let handle = || handler(app);
+    let state_file = args.state_file.clone();
+    let state_interval = Duration::from_secs(args.state_interval);
+    let reset_state = args.reset_state;
+    let journal_limit = args.journal_limit;
+    let bind_address = (args.host.clone(), args.port);
+    let versions = if args.http1_only {
+        HttpVersions::Http1Only
+    } else {
+        HttpVersions::Any
+    };
+    let client_auth =
+        args.client_ca
+            .as_deref()
+            .map(|ca| match mtls::ClientCertVerification::from_ca_file(ca) {
+                Ok(verification) => Arc::new(verification),
+                Err(err) => {
+                    eprintln!("Failed to set up --client-ca: {err}");
+                    std::process::exit(1);
+                }
+            });
+    let identity = match (&args.cert, &args.key) {
+        (Some(cert), Some(key)) => Some(tls::Identity::from_pem_files(cert, key)),
+        _ if args.tls || client_auth.is_some() => Some(tls::Identity::self_signed(&args.host)),
+        _ => None,
+    };
+    let tls_setup = identity.map(|identity| {
+        match identity.and_then(|identity| identity.server_config(versions, client_auth.clone())) {
+            Ok(setup) => setup,
+            Err(err) => {
+                eprintln!("Failed to set up HTTPS: {err}");
+                std::process::exit(1);
+            }
+        }
+    });
+    let mut config = ServerConfig {
+        proxy: args.proxy_to.as_deref().map(|upstream| {
+            Proxy::new(upstream).unwrap_or_else(|err| {
+                eprintln!("Failed to set up --proxy-to: {err}");
+                std::process::exit(1);
+            })
+        }),
+        recorder: args.record.clone().map(|file| {
+            match Recorder::open(file, args.record_generalize_ids, &args.record_headers) {
+                Ok(recorder) => Arc::new(recorder),
+                Err(err) => {
+                    eprintln!("Failed to set up --record: {err}");
+                    std::process::exit(1);
+                }
+            }
+        }),
+        client_auth: client_auth.as_ref().map(|_| mtls::ClientAuthPolicy {
+            rejection_status: StatusCode::from_u16(args.client_cert_status).unwrap(),
+        }),
+        cors: global_cors(&args),
+        jitter: Arc::new(Jitter::new(args.delay_seed)),
+        throttle: args.throttle,
+        first_byte: args.first_byte.clone(),
+        fallback: cli_fallback(&args),
+        file_cache: args.cache_files.then(Arc::default),
+        file_root: PathBuf::new(),
+    };
 
-            match requirement.http_method {
-                HttpMethods::GET => get(handle),
-                HttpMethods::POST => post(handle),
-                HttpMethods::PUT => put(handle),
-                HttpMethods::DELETE => delete(handle),
-                HttpMethods::PATCH => patch(handle),
+    let requirements = if let Some(import) = args.import {
+        let file = fs::read_to_string(&import).unwrap();
+        let base = Path::new(&import).parent().unwrap_or(Path::new(""));
+        let mut file = match tokenizer::parse_http_file(file) {
+            Ok(file) => file,
+            Err(err) => {
+                eprintln!("Failed to read {import}: {err}");
+                std::process::exit(1);
             }
         };
+        for requirement in &mut file.routes {
+            requirement.resolve_response_path(base);
+        }
+        config.file_root = base.to_path_buf();
+        // The flags take precedence over the file's FALLBACK block.
+        if let Some(mut fallback) = file.fallback.filter(|_| config.fallback.is_none()) {
+            if let Err(err) = fallback.resolve_file(base) {
+                eprintln!(
+                    "Failed to read the fallback file {}: {err}",
+                    fallback.file.as_deref().unwrap_or_default()
+                );
+                std::process::exit(1);
+            }
+            config.fallback = Some(fallback);
+        }
+        file.routes
+    } else {
+        vec![ApplicationRequirements::get_from_user(args)]
+    };
 
-        router = router.route(&requirement.path, route_handler());
-    }
+    let fixtures = ServerState {
+        routes: requirements,
+        ..ServerState::default()
+    };
+    let store = match StateStore::open(fixtures, state_file.clone(), reset_state) {
+        Ok(store) => store.with_journal_limit(journal_limit),
+        Err(err) => {
+            let path = state_file.unwrap_or_default();
+            eprintln!("Failed to restore the state from {}: {err}", path.display());
+            std::process::exit(1);
+        }
+    };
+    store.spawn_autosave(state_interval);
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:9999")
-        .await
-        .unwrap();
+    let server = MockServer::new(store.clone(), config);
 
-    println!("Server is running on http://localhost:9999");
+    let listener = match tokio::net::TcpListener::bind(&bind_address).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!(
+                "Failed to listen on {}:{}: {err}",
+                bind_address.0, bind_address.1
+            );
+            std::process::exit(1);
+        }
+    };
+
+    let scheme = if tls_setup.is_some() { "https" } else { "http" };
+    println!(
+        "Server is running on {scheme}://{}",
+        listener.local_addr().unwrap()
+    );
+    println!("Protocols: {}", versions.describe());
+    // In the order they are tried, see `match_order`.
     println!("Available routes:");
-    for requirement in &requirements {
+    let routes = server.routes();
+    for requirement in match_order(&routes) {
+        let priority = requirement
+            .priority
+            .map(|priority| format!(" - Priority: {priority}"))
+            .unwrap_or_default();
         println!(
-            "  {} {} - Status: {}",
+            "  {} {} - Status: {}{priority}",
             requirement.http_method, requirement.path, requirement.http_response_status
         );
     }
+    if let Some(proxy) = &server.config().proxy {
+        println!("  * Everything else is proxied to {}", proxy.upstream());
+    }
+    if let Some(recorder) = &server.config().recorder {
+        println!(
+            "Recording proxied responses into {}",
+            recorder.file().display()
+        );
+    }
+
+    serve::serve(
+        listener,
+        server.into_router(),
+        tls_setup,
+        versions,
+        shutdown_signal(),
+    )
+    .await;
+
+    if let Err(err) = store.persist().await {
+        eprintln!("Failed to save state: {err}");
+    }
+}
+
+/// The fallback from the `--fallback*` flags, `None` when none is given.
+fn cli_fallback(args: &Args) -> Option<Fallback> {
+    let given = args.fallback_status.is_some()
+        || args.fallback_body.is_some()
+        || !args.fallback_headers.is_empty()
+        || args.fallback_routes;
 
-    axum::serve(listener, router).await.unwrap();
+    given.then(|| Fallback {
+        status: args
+            .fallback_status
+            .unwrap_or(StatusCode::NOT_FOUND.as_u16()),
+        headers: args.fallback_headers.iter().cloned().collect(),
+        body: args.fallback_body.clone(),
+        file: None,
+        suggest_routes: args.fallback_routes,
+    })
 }
 
-async fn handler(app: ApplicationRequirements) -> impl IntoResponse {
-    app.try_sleep();
-
-    let status = StatusCode::from_u16(app.http_response_status).unwrap();
-
-    match (app.http_response_path, app.http_response_body) {
-        (Some(path), None) => Response::builder()
-            .status(status)
-            .body(Body::from(fs::read_to_string(path).unwrap()))
-            .unwrap(),
-        (None, Some(body)) => Response::builder()
-            .status(status)
-            .body(Body::from(body))
-            .unwrap(),
-        _ => Response::builder()
-            .status(status)
-            .body(Body::empty())
-            .unwrap(),
+fn parse_header(value: &str) -> Result<(String, String), String> {
+    tokenizer::ast::parse_header(value).map_err(|err| err.to_string())
+}
+
+/// CORS settings from the `--cors*` flags, `None` when none is given.
+fn global_cors(args: &Args) -> Option<CorsSettings> {
+    let cors = CorsSettings {
+        origins: args.cors_origins.clone(),
+        methods: args.cors_methods.clone(),
+        headers: args.cors_headers.clone(),
+        credentials: args.cors_credentials.then_some(true),
+        max_age: args.cors_max_age,
+    };
+
+    (args.cors || cors != CorsSettings::default()).then_some(cors)
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.unwrap();
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .unwrap()
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
     }
-}
\ No newline at end of file
+}
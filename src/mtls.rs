@@ -0,0 +1,350 @@
+use std::{path::Path, sync::Arc};
+
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use tokio_rustls::rustls::{
+    client::danger::HandshakeSignatureValid,
+    crypto::ring,
+    pki_types::{CertificateDer, UnixTime},
+    server::{
+        danger::{ClientCertVerified, ClientCertVerifier},
+        WebPkiClientVerifier,
+    },
+    DigitallySignedStruct, DistinguishedName, Error, RootCertStore, SignatureScheme,
+};
+
+use x509_parser::objects::{oid2abbrev, oid_registry};
+
+use crate::tls::TlsError;
+
+/// A certificate subject, e.g. `CN=alice, O=Acme`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Subject {
+    /// As x509-parser prints it.
+    pub text: String,
+    /// Its attributes, e.g. `("CN", "alice")`, in order.
+    pub attributes: Vec<(String, String)>,
+}
+
+impl Subject {
+    /// Whether every `TYPE=value` attribute of `required`, e.g. `CN=alice, O=Acme`,
+    /// is one of the subject's, types compared ignoring case and values exactly.
+    pub fn matches(&self, required: &str) -> bool {
+        required
+            .split([',', '+'])
+            .map(str::trim)
+            .filter(|attribute| !attribute.is_empty())
+            .all(|attribute| match attribute.split_once('=') {
+                Some((kind, value)) => self.attributes.iter().any(|(own_kind, own_value)| {
+                    own_kind.eq_ignore_ascii_case(kind.trim()) && own_value == value.trim()
+                }),
+                None => false,
+            })
+    }
+}
+
+/// Who is on the other end of a TLS connection, attached to each of its requests.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ClientIdentity {
+    Trusted {
+        subject: Subject,
+    },
+    Untrusted {
+        subject: Option<Subject>,
+        reason: String,
+    },
+    Missing,
+}
+
+impl ClientIdentity {
+    pub fn subject(&self) -> Option<&Subject> {
+        match self {
+            ClientIdentity::Trusted { subject } => Some(subject),
+            ClientIdentity::Untrusted { subject, .. } => subject.as_ref(),
+            ClientIdentity::Missing => None,
+        }
+    }
+
+    pub fn is_trusted(&self) -> bool {
+        matches!(self, ClientIdentity::Trusted { .. })
+    }
+
+    /// Why the request is refused when a trusted certificate is required.
+    pub fn rejection_reason(&self) -> String {
+        match self {
+            ClientIdentity::Trusted { .. } => String::new(),
+            ClientIdentity::Untrusted { reason, .. } => {
+                format!("Untrusted client certificate: {reason}")
+            }
+            ClientIdentity::Missing => "Missing client certificate".to_string(),
+        }
+    }
+}
+
+/// What happens to requests without a trusted client certificate.
+#[derive(Debug, Clone)]
+pub struct ClientAuthPolicy {
+    pub rejection_status: StatusCode,
+}
+
+impl Default for ClientAuthPolicy {
+    fn default() -> Self {
+        Self {
+            rejection_status: StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+impl ClientAuthPolicy {
+    /// Refuses clients without a trusted certificate, saying why.
+    pub fn check(&self, identity: Option<&ClientIdentity>) -> Result<(), String> {
+        match identity {
+            Some(identity) if identity.is_trusted() => Ok(()),
+            Some(identity) => Err(identity.rejection_reason()),
+            None => Err(ClientIdentity::Missing.rejection_reason()),
+        }
+    }
+
+    /// Refuses clients whose certificate subject doesn't have the attributes
+    /// of `required`, e.g. `CN=alice`.
+    pub fn check_subject(
+        &self,
+        identity: Option<&ClientIdentity>,
+        required: &str,
+    ) -> Result<(), String> {
+        self.check(identity)?;
+
+        match identity.and_then(ClientIdentity::subject) {
+            Some(subject) if subject.matches(required) => Ok(()),
+            Some(subject) => Err(format!(
+                "Client certificate subject {} doesn't match {required}",
+                subject.text
+            )),
+            None => Err(ClientIdentity::Missing.rejection_reason()),
+        }
+    }
+
+    /// The answer given to a refused client.
+    pub fn reject(&self, reason: String) -> Response {
+        (self.rejection_status, reason).into_response()
+    }
+}
+
+/// Asks clients for a certificate but lets the handshake through whatever
+/// they send, so the server can answer with an HTTP status instead of a TLS
+/// alert. Signatures are still checked, so a client can't present a
+/// certificate without holding its key.
+#[derive(Debug)]
+pub struct ClientCertVerification {
+    inner: Arc<dyn ClientCertVerifier>,
+}
+
+impl ClientCertVerification {
+    pub fn from_ca_file(path: &Path) -> Result<Self, TlsError> {
+        let mut roots = RootCertStore::empty();
+        for certificate in crate::tls::read_certificates(path)? {
+            roots.add(certificate)?;
+        }
+
+        Self::new(roots)
+    }
+
+    pub fn new(roots: RootCertStore) -> Result<Self, TlsError> {
+        let inner = WebPkiClientVerifier::builder_with_provider(
+            Arc::new(roots),
+            Arc::new(ring::default_provider()),
+        )
+        .allow_unauthenticated()
+        .build()?;
+
+        Ok(Self { inner })
+    }
+
+    pub fn identify(&self, certificates: Option<&[CertificateDer<'static>]>) -> ClientIdentity {
+        let Some((end_entity, intermediates)) =
+            certificates.and_then(|certificates| certificates.split_first())
+        else {
+            return ClientIdentity::Missing;
+        };
+
+        let subject = subject_of(end_entity);
+        match (
+            self.inner
+                .verify_client_cert(end_entity, intermediates, UnixTime::now()),
+            subject,
+        ) {
+            (Ok(_), Some(subject)) => ClientIdentity::Trusted { subject },
+            (Ok(_), None) => ClientIdentity::Untrusted {
+                subject: None,
+                reason: "unreadable subject".to_string(),
+            },
+            (Err(err), subject) => ClientIdentity::Untrusted {
+                subject,
+                reason: err.to_string(),
+            },
+        }
+    }
+}
+
+impl ClientCertVerifier for ClientCertVerification {
+    fn client_auth_mandatory(&self) -> bool {
+        false
+    }
+
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        self.inner.root_hint_subjects()
+    }
+
+    fn verify_client_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _now: UnixTime,
+    ) -> Result<ClientCertVerified, Error> {
+        Ok(ClientCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+fn subject_of(certificate: &CertificateDer<'_>) -> Option<Subject> {
+    let (_, certificate) = x509_parser::parse_x509_certificate(certificate).ok()?;
+    let name = certificate.subject();
+    let attributes = name
+        .iter_attributes()
+        .map(|attribute| {
+            let kind = oid2abbrev(attribute.attr_type(), oid_registry())
+                .map(str::to_string)
+                .unwrap_or_else(|_| attribute.attr_type().to_id_string());
+            // Values that aren't strings can't be required anyway.
+            let value = attribute.as_str().unwrap_or_default().to_string();
+            (kind, value)
+        })
+        .collect();
+
+    Some(Subject {
+        text: name.to_string(),
+        attributes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair};
+
+    fn certificate_authority() -> (rcgen::Certificate, KeyPair) {
+        let mut params = CertificateParams::new(Vec::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "testroute CA");
+        let key = KeyPair::generate().unwrap();
+        (params.self_signed(&key).unwrap(), key)
+    }
+
+    fn client_certificate(
+        name: &str,
+        issuer: Option<(&rcgen::Certificate, &KeyPair)>,
+    ) -> CertificateDer<'static> {
+        let mut params = CertificateParams::new(Vec::new()).unwrap();
+        params.distinguished_name.push(DnType::CommonName, name);
+        let key = KeyPair::generate().unwrap();
+        let certificate = match issuer {
+            Some((ca, ca_key)) => params.signed_by(&key, ca, ca_key).unwrap(),
+            None => params.self_signed(&key).unwrap(),
+        };
+        certificate.der().clone()
+    }
+
+    fn subject(name: &str) -> Subject {
+        Subject {
+            text: format!("CN={name}"),
+            attributes: vec![("CN".to_string(), name.to_string())],
+        }
+    }
+
+    #[test]
+    fn identify_tells_trusted_untrusted_and_missing_certificates_apart() {
+        let (ca, ca_key) = certificate_authority();
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.der().clone()).unwrap();
+        let verification = ClientCertVerification::new(roots).unwrap();
+
+        let trusted = client_certificate("alice", Some((&ca, &ca_key)));
+        assert_eq!(
+            verification.identify(Some(&[trusted])),
+            ClientIdentity::Trusted {
+                subject: subject("alice")
+            }
+        );
+
+        let untrusted = verification.identify(Some(&[client_certificate("mallory", None)]));
+        assert!(!untrusted.is_trusted());
+        assert_eq!(untrusted.subject(), Some(&subject("mallory")));
+
+        assert_eq!(verification.identify(None), ClientIdentity::Missing);
+    }
+
+    #[test]
+    fn policy_rejects_with_the_configured_status() {
+        let policy = ClientAuthPolicy {
+            rejection_status: StatusCode::UNAUTHORIZED,
+        };
+        let alice = ClientIdentity::Trusted {
+            subject: Subject {
+                text: "CN=alice, O=Acme".to_string(),
+                attributes: vec![
+                    ("CN".to_string(), "alice".to_string()),
+                    ("O".to_string(), "Acme".to_string()),
+                ],
+            },
+        };
+
+        assert!(policy.check(Some(&alice)).is_ok());
+        assert!(policy.check_subject(Some(&alice), "CN=alice").is_ok());
+        assert!(policy
+            .check_subject(Some(&alice), "o=Acme, CN=alice")
+            .is_ok());
+        // Attributes are compared whole, not as substrings.
+        assert!(policy.check_subject(Some(&alice), "CN=ali").is_err());
+        assert!(policy
+            .check_subject(Some(&alice), "CN=alice, O=Acme Corp")
+            .is_err());
+
+        let reason = policy.check_subject(Some(&alice), "CN=bob").unwrap_err();
+        assert_eq!(policy.reject(reason).status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            policy.check(None).unwrap_err(),
+            "Missing client certificate"
+        );
+
+        let untrusted = ClientIdentity::Untrusted {
+            subject: Some(subject("alice")),
+            reason: "unknown issuer".to_string(),
+        };
+        assert!(policy.check_subject(Some(&untrusted), "CN=alice").is_err());
+    }
+}
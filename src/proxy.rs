@@ -0,0 +1,174 @@
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, uri::PathAndQuery, HeaderMap, StatusCode, Uri},
+    response::{IntoResponse, Response},
+};
+use thiserror::Error;
+
+use crate::client::{self, HttpClient};
+
+#[derive(Error, Debug)]
+pub enum ProxyError {
+    #[error("Invalid upstream URL: {0}")]
+    InvalidUpstream(String),
+    #[error("Only http:// upstreams are supported: {0}")]
+    UnsupportedScheme(String),
+}
+
+/// Headers that only make sense for a single connection and must not be forwarded.
+const HOP_BY_HOP_HEADERS: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Forwards requests no mock matched to a real server.
+#[derive(Clone)]
+pub struct Proxy {
+    upstream: Uri,
+    client: HttpClient,
+}
+
+impl Proxy {
+    pub fn new(upstream: &str) -> Result<Self, ProxyError> {
+        let uri: Uri = upstream
+            .parse()
+            .map_err(|_| ProxyError::InvalidUpstream(upstream.to_string()))?;
+
+        match (uri.scheme_str(), uri.authority()) {
+            (Some("http"), Some(_)) => {}
+            (Some(_), Some(_)) => return Err(ProxyError::UnsupportedScheme(upstream.to_string())),
+            _ => return Err(ProxyError::InvalidUpstream(upstream.to_string())),
+        }
+
+        Ok(Self {
+            upstream: uri,
+            client: client::new(),
+        })
+    }
+
+    pub fn upstream(&self) -> &Uri {
+        &self.upstream
+    }
+
+    /// Sends the request upstream and streams the answer back as it arrives.
+    pub async fn forward(&self, request: Request) -> Response {
+        self.try_forward(request)
+            .await
+            .unwrap_or_else(|err| self.unreachable(err))
+    }
+
+    /// The answer given in place of the upstream's when it can't be reached.
+    pub fn unreachable(&self, err: impl std::fmt::Display) -> Response {
+        (
+            StatusCode::BAD_GATEWAY,
+            format!("Could not reach {}: {err}", self.upstream),
+        )
+            .into_response()
+    }
+
+    /// Like `forward`, but tells apart an unreachable upstream from its answers.
+    pub async fn try_forward(
+        &self,
+        request: Request,
+    ) -> Result<Response, hyper_util::client::legacy::Error> {
+        let (mut parts, body) = request.into_parts();
+
+        parts.uri = self.upstream_uri(&parts.uri);
+        strip_hop_by_hop_headers(&mut parts.headers);
+        parts.headers.remove(header::HOST);
+
+        let mut response = self
+            .client
+            .request(Request::from_parts(parts, body))
+            .await?
+            .map(Body::new);
+        strip_hop_by_hop_headers(response.headers_mut());
+
+        Ok(response)
+    }
+
+    fn upstream_uri(&self, uri: &Uri) -> Uri {
+        let base = self.upstream.path().trim_end_matches('/');
+        let path_and_query = uri.path_and_query().map_or("/", PathAndQuery::as_str);
+
+        let mut parts = self.upstream.clone().into_parts();
+        parts.path_and_query = format!("{base}{path_and_query}").parse().ok();
+        Uri::from_parts(parts).unwrap_or_else(|_| self.upstream.clone())
+    }
+}
+
+fn strip_hop_by_hop_headers(headers: &mut HeaderMap) {
+    for name in HOP_BY_HOP_HEADERS {
+        headers.remove(name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::to_bytes, routing::post, Router};
+
+    #[test]
+    fn proxy_only_accepts_http_upstreams() {
+        assert!(Proxy::new("http://localhost:8080").is_ok());
+        assert!(matches!(
+            Proxy::new("ftp://localhost:8080"),
+            Err(ProxyError::UnsupportedScheme(_))
+        ));
+        assert!(matches!(
+            Proxy::new("localhost"),
+            Err(ProxyError::InvalidUpstream(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn forward_sends_method_headers_and_body_upstream() {
+        let upstream = Router::new().route(
+            "/api/orders",
+            post(|headers: HeaderMap, uri: Uri, body: String| async move {
+                let token = headers["authorization"].to_str().unwrap().to_string();
+                (StatusCode::CREATED, format!("{token} {uri} {body}"))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, upstream).await });
+
+        let proxy = Proxy::new(&format!("http://{address}")).unwrap();
+        let response = proxy
+            .forward(
+                Request::builder()
+                    .method("POST")
+                    .uri("/api/orders?draft=true")
+                    .header("Authorization", "Bearer token")
+                    .body(Body::from(r#"{"id":1}"#))
+                    .unwrap(),
+            )
+            .await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, r#"Bearer token /api/orders?draft=true {"id":1}"#);
+    }
+
+    #[tokio::test]
+    async fn forward_answers_bad_gateway_when_the_upstream_is_down() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        drop(listener);
+
+        let proxy = Proxy::new(&format!("http://{address}")).unwrap();
+        let response = proxy
+            .forward(Request::builder().uri("/").body(Body::empty()).unwrap())
+            .await;
+
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    }
+}
@@ -0,0 +1,538 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, HeaderMap, HeaderName, StatusCode},
+    response::Response,
+};
+use futures_util::{stream, StreamExt};
+use thiserror::Error;
+
+use crate::{
+    app_requirements::{ApplicationRequirements, HttpMethods},
+    proxy::Proxy,
+    tokenizer::{self, ast::ParseError, writer},
+};
+
+/// Responses with a bigger body are passed on without being recorded.
+pub const RECORD_LIMIT: usize = 8 * 1024 * 1024;
+
+#[derive(Error, Debug)]
+pub enum RecordError {
+    #[error("Could not write the recording: {0}")]
+    Io(#[from] io::Error),
+    #[error("Could not read the existing recording: {0}")]
+    InvalidRecording(#[from] ParseError),
+    #[error("Invalid header name: {0}")]
+    InvalidHeaderName(String),
+}
+
+/// Proxies requests and writes a route for every new method and path it sees
+/// into a `.http` file, ready to be served with `--import`.
+pub struct Recorder {
+    file: PathBuf,
+    generalize_ids: bool,
+    headers: Vec<HeaderName>,
+    recorded: Mutex<Vec<ApplicationRequirements>>,
+}
+
+impl Recorder {
+    /// Keeps what `file` already holds, so a recording can be resumed.
+    pub fn open(
+        file: PathBuf,
+        generalize_ids: bool,
+        extra_headers: &[String],
+    ) -> Result<Self, RecordError> {
+        let recorded = match fs::read_to_string(&file) {
+            Ok(content) => tokenizer::parse_http(content)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        // An upstream may compress unasked, the body is only served right with its encoding.
+        let mut headers = vec![header::CONTENT_TYPE, header::CONTENT_ENCODING];
+        for name in extra_headers {
+            let name = HeaderName::from_str(name)
+                .map_err(|_| RecordError::InvalidHeaderName(name.clone()))?;
+            if !headers.contains(&name) {
+                headers.push(name);
+            }
+        }
+
+        Ok(Self {
+            file,
+            generalize_ids,
+            headers,
+            recorded: Mutex::new(recorded),
+        })
+    }
+
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// Streams the answer back as it arrives, and records it once all of it went
+    /// through, e.g. when an event stream ends.
+    pub async fn forward(self: &Arc<Self>, proxy: &Proxy, mut request: Request) -> Response {
+        // Asks for bodies as they should be served back, rather than compressed.
+        request.headers_mut().remove(header::ACCEPT_ENCODING);
+        let method = HttpMethods::from_str(request.method().as_str()).ok();
+        let path = self.route_path(request.uri().path());
+
+        let response = match proxy.try_forward(request).await {
+            Ok(response) => response,
+            Err(err) => return proxy.unreachable(err),
+        };
+
+        // Checked again by `record`, the routes may be recorded while the body is read.
+        let Some(method) = method.filter(|method| !self.is_recorded(method, &path)) else {
+            return response;
+        };
+        let too_big = response
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse::<usize>().ok())
+            .is_some_and(|length| length > RECORD_LIMIT);
+        if too_big {
+            return response;
+        }
+
+        let (parts, body) = response.into_parts();
+        let pending = PendingRoute {
+            recorder: Arc::clone(self),
+            method,
+            path,
+            status: parts.status,
+            headers: parts.headers.clone(),
+            body: Vec::new(),
+        };
+        let body = stream::unfold(
+            Some((body.into_data_stream(), Some(pending))),
+            |state| async move {
+                let (mut upstream, pending) = state?;
+                match upstream.next().await {
+                    Some(Ok(chunk)) => {
+                        // Past the limit the body is only passed on.
+                        let mut pending = pending
+                            .filter(|pending| pending.body.len() + chunk.len() <= RECORD_LIMIT);
+                        if let Some(pending) = &mut pending {
+                            pending.body.extend_from_slice(&chunk);
+                        }
+                        Some((Ok(chunk), Some((upstream, pending))))
+                    }
+                    // A body cut short isn't worth recording.
+                    Some(Err(err)) => Some((Err(err), None)),
+                    None => {
+                        if let Some(pending) = pending {
+                            pending.save().await;
+                        }
+                        None
+                    }
+                }
+            },
+        );
+
+        Response::from_parts(parts, Body::from_stream(body))
+    }
+
+    fn is_recorded(&self, method: &HttpMethods, path: &str) -> bool {
+        let recorded = self
+            .recorded
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        contains_route(&recorded, method, path)
+    }
+
+    /// Writes the route unless a concurrent request for it got there first.
+    fn record(
+        &self,
+        method: HttpMethods,
+        path: String,
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<(), RecordError> {
+        let mut requirement = ApplicationRequirements {
+            path,
+            http_method: method,
+            http_response_status: status.as_u16(),
+            ..ApplicationRequirements::default()
+        };
+
+        for name in &self.headers {
+            if let Some(value) = headers.get(name).and_then(|value| value.to_str().ok()) {
+                requirement
+                    .http_response_headers
+                    .insert(name.to_string(), value.to_string());
+            }
+        }
+
+        // Held until the recording is written, so a route is only ever written once.
+        let mut recorded = self
+            .recorded
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if contains_route(&recorded, &requirement.http_method, &requirement.path) {
+            return Ok(());
+        }
+
+        // JSON objects and lists are inlined, anything else goes to a file next
+        // to the recording because the `.http` format can't hold it.
+        match serde_json::from_slice::<serde_json::Value>(body) {
+            Ok(json) if json.is_object() || json.is_array() => {
+                requirement.http_response_body = Some(json.to_string());
+            }
+            _ if body.is_empty() => {}
+            _ => {
+                requirement.http_response_path =
+                    Some(self.write_body_file(&requirement, headers, body)?);
+            }
+        }
+
+        recorded.push(requirement);
+        fs::write(&self.file, writer::write_requirements(&recorded))?;
+
+        Ok(())
+    }
+
+    /// Returns the file path relative to the recording, as `File=` expects it.
+    fn write_body_file(
+        &self,
+        requirement: &ApplicationRequirements,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> io::Result<String> {
+        let stem = self
+            .file
+            .file_stem()
+            .map_or("recording".into(), |stem| stem.to_string_lossy());
+        let directory = format!("{stem}.bodies");
+
+        let name = format!("{}{}", requirement.http_method, requirement.path)
+            .to_lowercase()
+            .split(|ch: char| !ch.is_ascii_alphanumeric())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("-");
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        let relative = format!("{directory}/{name}.{}", extension_for(content_type));
+
+        let base = self.file.parent().unwrap_or(Path::new(""));
+        fs::create_dir_all(base.join(&directory))?;
+        fs::write(base.join(&relative), body)?;
+
+        Ok(relative)
+    }
+
+    fn route_path(&self, path: &str) -> String {
+        if self.generalize_ids {
+            generalize_ids(path)
+        } else {
+            path.to_string()
+        }
+    }
+}
+
+/// A proxied response on its way to the client, recorded once its body is complete.
+struct PendingRoute {
+    recorder: Arc<Recorder>,
+    method: HttpMethods,
+    path: String,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+impl PendingRoute {
+    async fn save(self) {
+        let saved = tokio::task::spawn_blocking(move || {
+            self.recorder.record(
+                self.method,
+                self.path,
+                self.status,
+                &self.headers,
+                &self.body,
+            )
+        })
+        .await
+        .unwrap_or_else(|err| Err(io::Error::other(err).into()));
+
+        if let Err(err) = saved {
+            eprintln!("Failed to record response: {err}");
+        }
+    }
+}
+
+fn contains_route(routes: &[ApplicationRequirements], method: &HttpMethods, path: &str) -> bool {
+    routes
+        .iter()
+        .any(|route| route.http_method == *method && route.path == path)
+}
+
+/// Turns numeric segments into route params: `/users/42/posts/7` becomes
+/// `/users/:id/posts/:id2`.
+pub fn generalize_ids(path: &str) -> String {
+    let mut params = 0;
+
+    path.split('/')
+        .map(|segment| {
+            if segment.is_empty() || !segment.chars().all(|ch| ch.is_ascii_digit()) {
+                return segment.to_string();
+            }
+
+            params += 1;
+            match params {
+                1 => ":id".to_string(),
+                n => format!(":id{n}"),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn extension_for(content_type: &str) -> &'static str {
+    let essence = content_type.split(';').next().unwrap_or_default().trim();
+
+    match essence {
+        "application/json" => "json",
+        "text/html" => "html",
+        "text/plain" => "txt",
+        "text/css" => "css",
+        "text/javascript" | "application/javascript" => "js",
+        "application/xml" | "text/xml" => "xml",
+        "application/pdf" => "pdf",
+        "image/png" => "png",
+        "image/jpeg" => "jpg",
+        "image/gif" => "gif",
+        "image/svg+xml" => "svg",
+        _ => "bin",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::{to_bytes, Bytes},
+        routing::get,
+        Router,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn generalize_ids_replaces_numeric_segments() {
+        assert_eq!(generalize_ids("/api/users/42"), "/api/users/:id");
+        assert_eq!(
+            generalize_ids("/users/42/posts/7/v2"),
+            "/users/:id/posts/:id2/v2"
+        );
+        assert_eq!(generalize_ids("/"), "/");
+    }
+
+    #[tokio::test]
+    async fn recorder_writes_each_route_once_into_an_importable_file() {
+        let upstream = Router::new()
+            .route(
+                "/api/users/:id",
+                get(|| async {
+                    (
+                        [(header::CONTENT_TYPE, "application/json")],
+                        r#"{"id": 42, "tags": ["a"]}"#,
+                    )
+                }),
+            )
+            .route(
+                "/api/users/:id/avatar",
+                get(|| async {
+                    (
+                        [(header::CONTENT_TYPE, "image/png")],
+                        vec![0x89, b'P', b'N', b'G', 0xff],
+                    )
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, upstream).await });
+
+        let directory =
+            std::env::temp_dir().join(format!("testroute-record-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let file = directory.join("recorded.http");
+
+        let proxy = Proxy::new(&format!("http://{address}")).unwrap();
+        let recorder = Arc::new(Recorder::open(file.clone(), true, &[]).unwrap());
+        for uri in ["/api/users/42", "/api/users/7", "/api/users/42/avatar"] {
+            let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            let response = recorder.forward(&proxy, request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        }
+
+        let routes = tokenizer::parse_http(fs::read_to_string(&file).unwrap()).unwrap();
+        assert_eq!(routes.len(), 2);
+        assert_eq!(routes[0].path, "/api/users/:id");
+        assert_eq!(
+            routes[0].http_response_body.as_deref(),
+            Some(r#"{"id":42,"tags":["a"]}"#)
+        );
+        assert_eq!(
+            routes[0]
+                .http_response_headers
+                .get("content-type")
+                .map(String::as_str),
+            Some("application/json")
+        );
+
+        let avatar = routes[1].http_response_path.as_deref().unwrap();
+        assert_eq!(avatar, "recorded.bodies/get-api-users-id-avatar.png");
+        assert_eq!(
+            fs::read(directory.join(avatar)).unwrap(),
+            vec![0x89, b'P', b'N', b'G', 0xff]
+        );
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn concurrent_first_requests_record_a_route_once() {
+        let file =
+            std::env::temp_dir().join(format!("testroute-record-once-{}.http", std::process::id()));
+        let _ = fs::remove_file(&file);
+        let recorder = Recorder::open(file.clone(), false, &[]).unwrap();
+
+        // Both requests got past `is_recorded` before either was recorded.
+        for body in [r#"{"id": 1}"#, r#"{"id": 2}"#] {
+            recorder
+                .record(
+                    HttpMethods::GET,
+                    "/api/users".to_string(),
+                    StatusCode::OK,
+                    &HeaderMap::new(),
+                    body.as_bytes(),
+                )
+                .unwrap();
+        }
+
+        let routes = tokenizer::parse_http(fs::read_to_string(&file).unwrap()).unwrap();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].http_response_body.as_deref(), Some(r#"{"id":1}"#));
+        fs::remove_file(file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn streams_are_passed_on_as_they_arrive_and_recorded_when_they_end() {
+        let (sender, receiver) = tokio::sync::mpsc::channel::<Result<Bytes, io::Error>>(1);
+        let events = Arc::new(Mutex::new(Some(receiver)));
+        let upstream = Router::new()
+            .route(
+                "/events",
+                get(move || async move {
+                    let receiver = events.lock().unwrap().take().unwrap();
+                    let events = stream::unfold(receiver, |mut receiver| async move {
+                        Some((receiver.recv().await?, receiver))
+                    });
+                    (
+                        [(header::CONTENT_TYPE, "text/event-stream")],
+                        Body::from_stream(events),
+                    )
+                }),
+            )
+            .route(
+                "/big",
+                get(|| async {
+                    // Streamed, so without a Content-Length telling the size upfront.
+                    let chunks = [RECORD_LIMIT, 1].map(|size| Ok::<_, io::Error>(vec![b'x'; size]));
+                    Body::from_stream(stream::iter(chunks))
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, upstream).await });
+
+        let file = std::env::temp_dir().join(format!(
+            "testroute-record-stream-{}.http",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&file);
+        let proxy = Proxy::new(&format!("http://{address}")).unwrap();
+        let recorder = Arc::new(Recorder::open(file.clone(), false, &[]).unwrap());
+
+        let request = Request::builder()
+            .uri("/events")
+            .body(Body::empty())
+            .unwrap();
+        let response =
+            tokio::time::timeout(Duration::from_secs(5), recorder.forward(&proxy, request))
+                .await
+                .expect("forward waited for the whole body");
+        let mut events = response.into_body().into_data_stream();
+        sender.send(Ok(Bytes::from("data: 1\n\n"))).await.unwrap();
+        let first = tokio::time::timeout(Duration::from_secs(5), events.next()).await;
+        assert_eq!(first.unwrap().unwrap().unwrap(), "data: 1\n\n");
+        assert!(!file.exists());
+
+        drop(sender);
+        while events.next().await.is_some() {}
+        let routes = tokenizer::parse_http(fs::read_to_string(&file).unwrap()).unwrap();
+        assert_eq!(routes.len(), 1);
+        assert_eq!(routes[0].path, "/events");
+
+        let request = Request::builder().uri("/big").body(Body::empty()).unwrap();
+        let response = recorder.forward(&proxy, request).await;
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body.len(), RECORD_LIMIT + 1);
+        let routes = tokenizer::parse_http(fs::read_to_string(&file).unwrap()).unwrap();
+        assert_eq!(routes.len(), 1);
+
+        let _ = fs::remove_dir_all(file.with_extension("bodies"));
+        fs::remove_file(file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn recorded_bodies_are_not_compressed() {
+        let upstream = Router::new().route(
+            "/api/users",
+            get(|headers: HeaderMap| async move {
+                axum::Json(serde_json::json!({
+                    "compressed": headers.contains_key(header::ACCEPT_ENCODING)
+                }))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, upstream).await });
+
+        let file = std::env::temp_dir().join(format!(
+            "testroute-record-encoding-{}.http",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&file);
+        let proxy = Proxy::new(&format!("http://{address}")).unwrap();
+        let recorder = Arc::new(Recorder::open(file.clone(), false, &[]).unwrap());
+
+        let request = Request::builder()
+            .uri("/api/users")
+            .header(header::ACCEPT_ENCODING, "gzip, br")
+            .body(Body::empty())
+            .unwrap();
+        let response = recorder.forward(&proxy, request).await;
+        to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        let routes = tokenizer::parse_http(fs::read_to_string(&file).unwrap()).unwrap();
+        assert_eq!(
+            routes[0].http_response_body.as_deref(),
+            Some(r#"{"compressed":false}"#)
+        );
+        fs::remove_file(file).unwrap();
+    }
+}
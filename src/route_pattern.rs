@@ -0,0 +1,323 @@
+use std::cmp::Ordering;
+
+use percent_encoding::percent_decode_str;
+use regex::Regex;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum InvalidPattern {
+    #[error("Invalid regex in route path {0}: {1}")]
+    Regex(String, String),
+    #[error("Unclosed parenthesis in route path: {0}")]
+    UnclosedParenthesis(String),
+    #[error("Catch-all segments must come last in route path: {0}")]
+    CatchAllNotLast(String),
+    #[error("Route path segment without a name: {0}")]
+    MissingName(String),
+}
+
+/// The values a request path gave the `:params` and `*rest` of the route it
+/// matched, in the order they appear in the pattern.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct PathCaptures(pub Vec<(String, String)>);
+
+impl PathCaptures {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(capture, _)| capture == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal {
+        text: String,
+        optional: bool,
+    },
+    Param {
+        name: String,
+        regex: Option<Regex>,
+        optional: bool,
+    },
+    /// `*rest`, the remaining segments, possibly none.
+    CatchAll(String),
+}
+
+impl Segment {
+    fn optional(&self) -> bool {
+        match self {
+            Segment::Literal { optional, .. } | Segment::Param { optional, .. } => *optional,
+            Segment::CatchAll(_) => true,
+        }
+    }
+
+    /// Lower is more specific: literals, then regex params, then params, then catch-alls.
+    fn rank(&self) -> u8 {
+        let rank = match self {
+            Segment::Literal { .. } => 0,
+            Segment::Param { regex: Some(_), .. } => 2,
+            Segment::Param { regex: None, .. } => 4,
+            Segment::CatchAll(_) => return 8,
+        };
+        rank + u8::from(self.optional())
+    }
+}
+
+/// A route path: literal segments, `:params`, `:params(regex)` constrained
+/// to a single segment, segments made optional with a trailing `?`, and a
+/// `*rest` catch-all at the end.
+#[derive(Debug, Clone)]
+pub struct PathPattern {
+    segments: Vec<Segment>,
+}
+
+impl PathPattern {
+    pub fn parse(pattern: &str) -> Result<Self, InvalidPattern> {
+        let raw_segments = split_segments(pattern)?;
+        let last = raw_segments.len().saturating_sub(1);
+
+        let segments = raw_segments
+            .into_iter()
+            .enumerate()
+            .map(|(index, raw)| {
+                let (raw, optional) = match raw.strip_suffix('?') {
+                    Some(raw) => (raw, true),
+                    None => (raw, false),
+                };
+
+                if let Some(name) = raw.strip_prefix('*') {
+                    if index != last {
+                        return Err(InvalidPattern::CatchAllNotLast(pattern.to_string()));
+                    }
+                    return Ok(Segment::CatchAll(name.to_string()));
+                }
+
+                let Some(param) = raw.strip_prefix(':') else {
+                    return Ok(Segment::Literal {
+                        text: raw.to_string(),
+                        optional,
+                    });
+                };
+
+                let (name, regex) = match param.split_once('(') {
+                    Some((name, regex)) => {
+                        let regex = regex.strip_suffix(')').unwrap_or(regex);
+                        let compiled = Regex::new(&format!("^(?:{regex})$")).map_err(|err| {
+                            InvalidPattern::Regex(pattern.to_string(), err.to_string())
+                        })?;
+                        (name, Some(compiled))
+                    }
+                    None => (param, None),
+                };
+                if name.is_empty() {
+                    return Err(InvalidPattern::MissingName(pattern.to_string()));
+                }
+
+                Ok(Segment::Param {
+                    name: name.to_string(),
+                    regex,
+                    optional,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { segments })
+    }
+
+    /// What the `:params` and `*rest` of the pattern took from `path`, `None`
+    /// when `path` doesn't match.
+    pub fn captures(&self, path: &str) -> Option<PathCaptures> {
+        let path = path.strip_prefix('/').unwrap_or(path);
+        let segments: Vec<&str> = path.split('/').collect();
+        let mut captures = Vec::new();
+
+        match_segments(&self.segments, &segments, &mut captures).then_some(PathCaptures(captures))
+    }
+
+    /// Orders patterns from the most to the least specific, so `/users/new`
+    /// comes before `/users/:id(\d+)`, before `/users/:id`, before `/users/*rest`.
+    pub fn cmp_specificity(&self, other: &Self) -> Ordering {
+        let ranks = |pattern: &Self| {
+            pattern
+                .segments
+                .iter()
+                .map(Segment::rank)
+                .collect::<Vec<_>>()
+        };
+        ranks(self).cmp(&ranks(other))
+    }
+}
+
+/// Splits on the slashes that aren't inside a regex.
+fn split_segments(pattern: &str) -> Result<Vec<&str>, InvalidPattern> {
+    let pattern_body = pattern.strip_prefix('/').unwrap_or(pattern);
+    let mut segments = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+
+    for (index, ch) in pattern_body.char_indices() {
+        match ch {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            '/' if depth == 0 => {
+                segments.push(&pattern_body[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err(InvalidPattern::UnclosedParenthesis(pattern.to_string()));
+    }
+    segments.push(&pattern_body[start..]);
+
+    Ok(segments)
+}
+
+fn match_segments(
+    pattern: &[Segment],
+    path: &[&str],
+    captures: &mut Vec<(String, String)>,
+) -> bool {
+    let Some((segment, pattern_rest)) = pattern.split_first() else {
+        return path.is_empty();
+    };
+
+    if let Segment::CatchAll(name) = segment {
+        let rest: Vec<String> = path.iter().map(|segment| decode(segment)).collect();
+        captures.push((name.clone(), rest.join("/")));
+        return true;
+    }
+
+    if let Some((value, path_rest)) = path.split_first() {
+        let value = decode(value);
+        let matches = match segment {
+            Segment::Literal { text, .. } => *text == value,
+            Segment::Param { regex, .. } => {
+                !value.is_empty() && regex.as_ref().is_none_or(|regex| regex.is_match(&value))
+            }
+            Segment::CatchAll(_) => unreachable!("catch-alls are matched above"),
+        };
+
+        if matches {
+            let captured = captures.len();
+            if let Segment::Param { name, .. } = segment {
+                captures.push((name.clone(), value));
+            }
+            if match_segments(pattern_rest, path_rest, captures) {
+                return true;
+            }
+            captures.truncate(captured);
+        }
+    }
+
+    // An optional segment may just as well not be there.
+    segment.optional() && match_segments(pattern_rest, path, captures)
+}
+
+fn decode(segment: &str) -> String {
+    percent_decode_str(segment).decode_utf8_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn captures(pattern: &str, path: &str) -> Option<Vec<(String, String)>> {
+        PathPattern::parse(pattern)
+            .unwrap()
+            .captures(path)
+            .map(|captures| captures.0)
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Option<Vec<(String, String)>> {
+        Some(
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn params_regexes_and_catch_alls_capture() {
+        assert_eq!(captures("/users/:id", "/users/42"), pairs(&[("id", "42")]));
+        assert_eq!(captures("/users/:id", "/users/"), None);
+        assert_eq!(
+            captures(r"/users/:id(\d+)", "/users/42"),
+            pairs(&[("id", "42")])
+        );
+        assert_eq!(captures(r"/users/:id(\d+)", "/users/abc"), None);
+        assert_eq!(
+            captures("/files/*path", "/files/a/b%20c.txt"),
+            pairs(&[("path", "a/b c.txt")])
+        );
+        assert_eq!(captures("/files/*path", "/files"), pairs(&[("path", "")]));
+        assert_eq!(captures("/", "/"), pairs(&[]));
+        assert_eq!(captures("/users", "/users/42"), None);
+    }
+
+    #[test]
+    fn optional_segments_may_be_left_out() {
+        assert_eq!(captures("/api/v2?/users/:id?", "/api/users"), pairs(&[]));
+        assert_eq!(
+            captures("/api/v2?/users/:id?", "/api/v2/users/7"),
+            pairs(&[("id", "7")])
+        );
+        assert_eq!(
+            captures(r"/posts/:year(\d{4})?/:slug", "/posts/2024/hello"),
+            pairs(&[("year", "2024"), ("slug", "hello")])
+        );
+        assert_eq!(
+            captures(r"/posts/:year(\d{4})?/:slug", "/posts/hello"),
+            pairs(&[("slug", "hello")])
+        );
+    }
+
+    #[test]
+    fn invalid_patterns_are_refused() {
+        assert!(matches!(
+            PathPattern::parse("/files/*path/raw"),
+            Err(InvalidPattern::CatchAllNotLast(_))
+        ));
+        assert!(matches!(
+            PathPattern::parse(r"/users/:id(\d+"),
+            Err(InvalidPattern::UnclosedParenthesis(_))
+        ));
+        assert!(matches!(
+            PathPattern::parse("/users/:id([)"),
+            Err(InvalidPattern::Regex(..))
+        ));
+        assert!(matches!(
+            PathPattern::parse("/users/:"),
+            Err(InvalidPattern::MissingName(_))
+        ));
+    }
+
+    #[test]
+    fn specific_patterns_sort_first() {
+        let mut patterns = [
+            "/users/*rest",
+            "/users/:id",
+            r"/users/:id(\d+)",
+            "/users/new",
+        ];
+        patterns.sort_by(|a, b| {
+            PathPattern::parse(a)
+                .unwrap()
+                .cmp_specificity(&PathPattern::parse(b).unwrap())
+        });
+
+        assert_eq!(
+            patterns,
+            [
+                "/users/new",
+                r"/users/:id(\d+)",
+                "/users/:id",
+                "/users/*rest"
+            ]
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use std::{future::Future, pin::Pin};
+
+use axum::{extract::Request, Router};
+use hyper::server::conn::http1;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+    service::TowerToHyperService,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+    sync::watch,
+};
+use tower::ServiceExt;
+
+use crate::{
+    fault::{ConnectionControl, FaultyIo, TcpSocket},
+    tls::TlsSetup,
+};
+
+/// HTTP versions a connection may speak.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum HttpVersions {
+    /// HTTP/1.1, plus HTTP/2 negotiated through ALPN over TLS or sent with
+    /// prior knowledge (h2c) over plain TCP.
+    #[default]
+    Any,
+    /// HTTP/1.1 only, see `--http1-only`.
+    Http1Only,
+}
+
+impl HttpVersions {
+    /// Protocols offered during the TLS handshake, most preferred first.
+    pub fn alpn_protocols(self) -> Vec<Vec<u8>> {
+        match self {
+            HttpVersions::Any => vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+            HttpVersions::Http1Only => vec![b"http/1.1".to_vec()],
+        }
+    }
+
+    pub fn describe(self) -> &'static str {
+        match self {
+            HttpVersions::Any => "HTTP/1.1, HTTP/2",
+            HttpVersions::Http1Only => "HTTP/1.1",
+        }
+    }
+}
+
+/// Serves the router until `shutdown` resolves, then waits for the open
+/// connections to finish what they are doing. Every request carries its
+/// connection's `ConnectionControl` as an extension, and with TLS client auth
+/// on, its `ClientIdentity` too.
+pub async fn serve(
+    listener: TcpListener,
+    router: Router,
+    tls: Option<TlsSetup>,
+    versions: HttpVersions,
+    shutdown: impl Future<Output = ()>,
+) {
+    let acceptor = tls
+        .as_ref()
+        .map(|setup| tokio_rustls::TlsAcceptor::from(setup.config.clone()));
+    let client_auth = tls.and_then(|setup| setup.client_auth);
+    let (signal_tx, signal_rx) = watch::channel(());
+    let (close_tx, close_rx) = watch::channel(());
+
+    tokio::pin!(shutdown);
+
+    loop {
+        let stream = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    eprintln!("Failed to accept connection: {err}");
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+
+        let acceptor = acceptor.clone();
+        let client_auth = client_auth.clone();
+        let router = router.clone();
+        let signal_rx = signal_rx.clone();
+        let close_rx = close_rx.clone();
+
+        // The handshake runs in the connection's task so a slow client can't
+        // hold up the accept loop.
+        tokio::spawn(async move {
+            let Some(acceptor) = acceptor else {
+                serve_connection(stream, router, versions, signal_rx).await;
+                drop(close_rx);
+                return;
+            };
+
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(_) => return,
+            };
+
+            let identity = client_auth
+                .map(|verification| verification.identify(stream.get_ref().1.peer_certificates()));
+            let router = match identity {
+                Some(identity) => with_extension(router, identity),
+                None => router,
+            };
+
+            serve_connection(stream, router, versions, signal_rx).await;
+            drop(close_rx);
+        });
+    }
+
+    drop(signal_rx);
+    drop(close_rx);
+    let _ = signal_tx.send(());
+    close_tx.closed().await;
+}
+
+fn with_extension<T: Clone + Send + Sync + 'static>(router: Router, value: T) -> Router {
+    Router::new().fallback_service(router.map_request(move |mut request: Request| {
+        request.extensions_mut().insert(value.clone());
+        request
+    }))
+}
+
+async fn serve_connection<I>(
+    io: I,
+    router: Router,
+    versions: HttpVersions,
+    signal_rx: watch::Receiver<()>,
+) where
+    I: AsyncRead + AsyncWrite + TcpSocket + Unpin + Send + 'static,
+{
+    let control = ConnectionControl::new(signal_rx.clone());
+    let io = TokioIo::new(FaultyIo::new(io, control.clone()));
+    let service = TowerToHyperService::new(with_extension(router, control));
+
+    // The auto builder ignores `http1_only` once upgrades are on, so forcing
+    // HTTP/1.1 goes through hyper's own HTTP/1 connection.
+    match versions {
+        HttpVersions::Any => {
+            let builder = auto::Builder::new(TokioExecutor::new());
+            let connection = builder.serve_connection_with_upgrades(io, service);
+            until_shutdown(
+                connection,
+                auto::UpgradeableConnection::graceful_shutdown,
+                signal_rx,
+            )
+            .await;
+        }
+        HttpVersions::Http1Only => {
+            let connection = http1::Builder::new()
+                .serve_connection(io, service)
+                .with_upgrades();
+            until_shutdown(
+                connection,
+                http1::UpgradeableConnection::graceful_shutdown,
+                signal_rx,
+            )
+            .await;
+        }
+    }
+}
+
+async fn until_shutdown<C: Future>(
+    connection: C,
+    graceful_shutdown: fn(Pin<&mut C>),
+    mut signal_rx: watch::Receiver<()>,
+) {
+    tokio::pin!(connection);
+
+    tokio::select! {
+        _ = connection.as_mut() => {}
+        _ = signal_rx.changed() => {
+            graceful_shutdown(connection.as_mut());
+            let _ = connection.as_mut().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Version};
+    use hyper_util::client::legacy::Client;
+
+    async fn spawn(versions: HttpVersions) -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let router = Router::new()
+            .fallback(|request: Request| async move { format!("{:?}", request.version()) });
+        tokio::spawn(serve(
+            listener,
+            router,
+            None,
+            versions,
+            std::future::pending(),
+        ));
+        address
+    }
+
+    fn get(address: std::net::SocketAddr) -> Request {
+        Request::builder()
+            .uri(format!("http://{address}/"))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn serve_accepts_h2c_with_prior_knowledge() {
+        let address = spawn(HttpVersions::Any).await;
+        let h2c = Client::builder(TokioExecutor::new())
+            .http2_only(true)
+            .build_http();
+
+        let response = h2c.request(get(address)).await.unwrap();
+
+        assert_eq!(response.version(), Version::HTTP_2);
+        let body = axum::body::to_bytes(Body::new(response.into_body()), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "HTTP/2.0");
+    }
+
+    #[tokio::test]
+    async fn http1_only_refuses_h2c() {
+        let address = spawn(HttpVersions::Http1Only).await;
+        let h2c = Client::builder(TokioExecutor::new())
+            .http2_only(true)
+            .build_http::<Body>();
+        assert!(h2c.request(get(address)).await.is_err());
+
+        let response = crate::client::new().request(get(address)).await.unwrap();
+        assert_eq!(response.version(), Version::HTTP_11);
+    }
+}
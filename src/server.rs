@@ -0,0 +1,1200 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+    extract::WebSocketUpgrade,
+    http::{header, request::Parts, HeaderMap, Method, Response, StatusCode},
+    middleware,
+    response::IntoResponse,
+    routing::{MethodFilter, MethodRouter},
+    Router,
+};
+use tower::ServiceExt;
+
+use crate::{
+    admin,
+    app_requirements::{ApplicationRequirements, HttpMethods},
+    compiled_route::CompiledRoute,
+    cors::CorsSettings,
+    delay::{Delay, Jitter},
+    fallback::Fallback,
+    fault::{self, ConnectionControl, Injected},
+    file_body::{parse_range, FileBody, FileCache, RangeRequest},
+    journal::{self, MatchedRoute},
+    mtls::{ClientAuthPolicy, ClientIdentity},
+    proxy::Proxy,
+    record::Recorder,
+    route_pattern::{PathCaptures, PathPattern},
+    state::StateStore,
+    template,
+    throttle::{self, Bandwidth},
+};
+
+/// Settings that apply to the whole server rather than to a single route.
+#[derive(Clone, Default)]
+pub struct ServerConfig {
+    /// Where requests that match no mock are forwarded to.
+    pub proxy: Option<Proxy>,
+    /// Writes down what the proxy answered, see `--record`.
+    pub recorder: Option<Arc<Recorder>>,
+    /// Set when mocks are only served to clients with a trusted certificate.
+    pub client_auth: Option<ClientAuthPolicy>,
+    /// Cross-origin settings for every route, see `--cors`.
+    pub cors: Option<CorsSettings>,
+    /// Draws the delays of the routes, see `--delay-seed`.
+    pub jitter: Arc<Jitter>,
+    /// Throughput every body is streamed at, see `--throttle`.
+    pub throttle: Option<Bandwidth>,
+    /// Wait before the first body byte of every response, see `--first-byte`.
+    pub first_byte: Option<Delay>,
+    /// Answers the requests no route matches, unless they are proxied.
+    pub fallback: Option<Fallback>,
+    /// Keeps response files in memory, see `--cache-files`.
+    pub file_cache: Option<Arc<FileCache>>,
+    /// The directory of the imported `.http` file, the only one routes added
+    /// through the admin API may serve files from.
+    pub file_root: PathBuf,
+}
+
+/// The running mock server: the routes live in the server state and are
+/// compiled into an axum router again every time they change.
+#[derive(Clone)]
+pub struct MockServer {
+    pub store: StateStore,
+    config: Arc<ServerConfig>,
+    router: Arc<RwLock<Router>>,
+}
+
+impl MockServer {
+    pub fn new(store: StateStore, config: ServerConfig) -> Self {
+        let config = Arc::new(config);
+        let router = build_router(&store.snapshot().routes, &store, &config);
+
+        Self {
+            store,
+            config,
+            router: Arc::new(RwLock::new(router)),
+        }
+    }
+
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    pub fn routes(&self) -> Vec<ApplicationRequirements> {
+        self.store.lock().routes.clone()
+    }
+
+    /// Changes the route set and swaps in a router serving the result.
+    pub fn update_routes<T>(
+        &self,
+        change: impl FnOnce(&mut Vec<ApplicationRequirements>) -> T,
+    ) -> T {
+        let (result, routes) = {
+            let mut state = self.store.lock();
+            let result = change(&mut state.routes);
+            (result, state.routes.clone())
+        };
+
+        self.swap_router(&routes);
+        result
+    }
+
+    /// Picks up route changes made to the state directly, e.g. by a reset.
+    pub fn reload(&self) {
+        self.swap_router(&self.routes());
+    }
+
+    fn swap_router(&self, routes: &[ApplicationRequirements]) {
+        let router = build_router(routes, &self.store, &self.config);
+        *self
+            .router
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = router;
+    }
+
+    fn current_router(&self) -> Router {
+        self.router
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// The service to hand to `axum::serve`: admin endpoints, then the mocks.
+    pub fn into_router(self) -> Router {
+        let store = self.store.clone();
+        let mocks = self.clone();
+
+        Router::new()
+            .nest(admin::ADMIN_PREFIX, admin::router(self))
+            .fallback(move |request: Request| async move {
+                if let Some(policy) = &mocks.config.client_auth {
+                    if let Err(reason) = policy.check(request.extensions().get::<ClientIdentity>())
+                    {
+                        return policy.reject(reason);
+                    }
+                }
+
+                mocks
+                    .current_router()
+                    .oneshot(request)
+                    .await
+                    .into_response()
+            })
+            .layer(middleware::from_fn_with_state(store, journal::record))
+    }
+}
+
+fn build_router(
+    requirements: &[ApplicationRequirements],
+    store: &StateStore,
+    config: &Arc<ServerConfig>,
+) -> Router {
+    let table = Arc::new(route_table(requirements, store, config));
+    let unmatched = unmatched_router(requirements, config);
+
+    Router::new().fallback(move |mut request: Request| async move {
+        let path = request.uri().path().to_string();
+        let mut path_matched = None;
+        let mut chosen: Option<(i32, &PathRoutes, PathCaptures)> = None;
+
+        for entry in table.iter() {
+            let Some(captures) = entry.pattern.captures(&path) else {
+                continue;
+            };
+            match entry.priority(request.method()) {
+                // Equal priorities go to the more specific path, then to the first declared.
+                Some(priority) if chosen.as_ref().is_none_or(|(best, ..)| priority > *best) => {
+                    chosen = Some((priority, entry, captures));
+                }
+                Some(_) => {}
+                None => {
+                    path_matched.get_or_insert(entry);
+                }
+            }
+        }
+
+        match (chosen, path_matched) {
+            (Some((_, entry, captures)), _) => {
+                request.extensions_mut().insert(captures);
+                entry.router.clone().oneshot(request).await.into_response()
+            }
+            // Answers 405 with the methods the path does serve.
+            (None, Some(entry)) => entry.router.clone().oneshot(request).await.into_response(),
+            (None, None) => unmatched.oneshot(request).await.into_response(),
+        }
+    })
+}
+
+/// The routes of one path pattern, served together.
+struct PathRoutes {
+    pattern: PathPattern,
+    /// The method and priority of each route.
+    methods: Vec<(HttpMethods, i32)>,
+    preflight: bool,
+    router: MethodRouter,
+}
+
+impl PathRoutes {
+    /// The priority of the route serving `method`, if any does.
+    fn priority(&self, method: &Method) -> Option<i32> {
+        let exact = self.methods.iter().find(|(served, _)| match served {
+            HttpMethods::ANY => false,
+            // axum's GET routes answer HEAD requests too, dropping the body.
+            HttpMethods::GET | HttpMethods::WS => method == Method::GET || method == Method::HEAD,
+            served => served.to_string() == method.as_str(),
+        });
+        let any = || {
+            self.methods
+                .iter()
+                .find(|(served, _)| *served == HttpMethods::ANY)
+        };
+
+        match exact.or_else(any) {
+            Some((_, priority)) => Some(*priority),
+            None if self.preflight && method == Method::OPTIONS => {
+                self.methods.iter().map(|(_, priority)| *priority).max()
+            }
+            None => None,
+        }
+    }
+}
+
+/// The order routes are tried in: the highest `Priority=` first, then the
+/// most specific path, then the first declared.
+pub fn match_order(requirements: &[ApplicationRequirements]) -> Vec<&ApplicationRequirements> {
+    let patterns: Vec<Option<PathPattern>> = requirements
+        .iter()
+        .map(|route| PathPattern::parse(&route.path).ok())
+        .collect();
+    let mut order: Vec<usize> = (0..requirements.len()).collect();
+    order.sort_by(|&a, &b| {
+        let by_priority = requirements[b].priority().cmp(&requirements[a].priority());
+        let by_specificity = match (&patterns[a], &patterns[b]) {
+            (Some(a), Some(b)) => a.cmp_specificity(b),
+            (a, b) => b.is_some().cmp(&a.is_some()),
+        };
+        by_priority.then(by_specificity)
+    });
+
+    order
+        .into_iter()
+        .map(|index| &requirements[index])
+        .collect()
+}
+
+/// The paths to try in turn, the most specific first, e.g. `/users/new`
+/// before `/users/:id`. Paths equally specific keep their order; priorities
+/// are weighed per request, as they depend on the method.
+fn route_table(
+    requirements: &[ApplicationRequirements],
+    store: &StateStore,
+    config: &Arc<ServerConfig>,
+) -> Vec<PathRoutes> {
+    let mut table: Vec<PathRoutes> = group_by_path(requirements)
+        .into_iter()
+        .filter_map(|(path, routes)| {
+            // The parser and the admin API refuse these already.
+            let pattern = PathPattern::parse(path)
+                .map_err(|err| eprintln!("Skipping {path}: {err}"))
+                .ok()?;
+            Some(PathRoutes {
+                pattern,
+                methods: routes
+                    .iter()
+                    .map(|route| (route.http_method.clone(), route.priority()))
+                    .collect(),
+                preflight: needs_preflight(&routes, config),
+                router: path_router(routes, store, config),
+            })
+        })
+        .collect();
+    table.sort_by(|a, b| a.pattern.cmp_specificity(&b.pattern));
+
+    table
+}
+
+/// Answers the requests no path matches: the proxy, the fallback, or an empty 404.
+fn unmatched_router(requirements: &[ApplicationRequirements], config: &ServerConfig) -> Router {
+    if let Some(proxy) = config.proxy.clone() {
+        let recorder = config.recorder.clone();
+        Router::new().fallback(move |request: Request| async move {
+            match recorder {
+                Some(recorder) => recorder.forward(&proxy, request).await,
+                None => proxy.forward(request).await,
+            }
+        })
+    } else if let Some(fallback) = config.fallback.clone() {
+        let routes = requirements.to_vec();
+        Router::new().fallback(move |request: Parts| async move {
+            fallback.respond(&request, &routes).await
+        })
+    } else {
+        Router::new()
+    }
+}
+
+/// The routes of every path, in order. Only the first route of a method and
+/// path is kept; the `.http` parser and the admin API refuse the others
+/// anyway.
+fn group_by_path(
+    requirements: &[ApplicationRequirements],
+) -> Vec<(&str, Vec<ApplicationRequirements>)> {
+    let mut paths: Vec<(&str, Vec<ApplicationRequirements>)> = Vec::new();
+
+    for requirement in requirements {
+        match paths.iter_mut().find(|(path, _)| *path == requirement.path) {
+            Some((_, routes))
+                if routes
+                    .iter()
+                    .any(|route| route.http_method == requirement.http_method) => {}
+            Some((_, routes)) => routes.push(requirement.clone()),
+            None => paths.push((&requirement.path, vec![requirement.clone()])),
+        }
+    }
+
+    paths
+}
+
+fn path_router(
+    routes: Vec<ApplicationRequirements>,
+    store: &StateStore,
+    config: &Arc<ServerConfig>,
+) -> MethodRouter {
+    let mut method_router = MethodRouter::new();
+    let websocket = routes
+        .iter()
+        .find(|route| route.http_method == HttpMethods::WS)
+        .map(|route| Arc::new(CompiledRoute::new(route.clone(), config)));
+    let serves_get = routes
+        .iter()
+        .any(|route| route.http_method == HttpMethods::GET);
+    let mut other_methods = Vec::new();
+
+    for route in &routes {
+        // axum's GET routes answer HEAD requests too, dropping the body.
+        let filter = match &route.http_method {
+            HttpMethods::GET | HttpMethods::WS => MethodFilter::GET,
+            HttpMethods::POST => MethodFilter::POST,
+            HttpMethods::PUT => MethodFilter::PUT,
+            HttpMethods::DELETE => MethodFilter::DELETE,
+            HttpMethods::PATCH => MethodFilter::PATCH,
+            HttpMethods::HEAD => MethodFilter::HEAD,
+            HttpMethods::OPTIONS => MethodFilter::OPTIONS,
+            HttpMethods::TRACE => MethodFilter::TRACE,
+            // axum only routes standard methods, the others go through `dispatch`.
+            HttpMethods::ANY | HttpMethods::Custom(_) => {
+                other_methods.push(Arc::new(CompiledRoute::new(route.clone(), config)));
+                continue;
+            }
+        };
+
+        let app = Arc::new(CompiledRoute::new(route.clone(), config));
+        let (store, config) = (store.clone(), config.clone());
+        method_router = match (&route.http_method, websocket.clone()) {
+            // A path serving both answers handshakes over WebSocket and the rest over HTTP.
+            (HttpMethods::GET, Some(websocket)) => method_router.on(
+                filter,
+                |upgrade: Option<WebSocketUpgrade>, request: Parts| async move {
+                    match upgrade {
+                        Some(upgrade) => websocket_handler(websocket, store, config, upgrade).await,
+                        None => handler(app, store, config, request).await,
+                    }
+                },
+            ),
+            (HttpMethods::WS, _) if serves_get => continue,
+            (HttpMethods::WS, _) => method_router.on(filter, |upgrade: WebSocketUpgrade| {
+                websocket_handler(app, store, config, upgrade)
+            }),
+            _ => method_router.on(filter, |request: Parts| {
+                handler(app, store, config, request)
+            }),
+        };
+    }
+
+    if !other_methods.is_empty() {
+        let (other_methods, store, config) =
+            (Arc::new(other_methods), store.clone(), config.clone());
+        method_router = method_router
+            .fallback(|request: Parts| dispatch(other_methods, store, config, request));
+    }
+
+    if needs_preflight(&routes, config) {
+        let http_routes: Vec<ApplicationRequirements> = routes
+            .into_iter()
+            .filter(|route| route.http_method != HttpMethods::WS)
+            .collect();
+        let config = config.clone();
+        method_router =
+            method_router.options(|headers: HeaderMap| preflight(http_routes, config, headers));
+    }
+
+    method_router
+}
+
+/// Browsers send a preflight OPTIONS before most cross-origin calls, unless
+/// the path mocks OPTIONS itself. WebSocket handshakes aren't preflighted.
+fn needs_preflight(routes: &[ApplicationRequirements], config: &ServerConfig) -> bool {
+    let http_routes = || {
+        routes
+            .iter()
+            .filter(|route| route.http_method != HttpMethods::WS)
+    };
+    let has_cors = http_routes()
+        .any(|route| CorsSettings::resolve(config.cors.as_ref(), route.cors.as_ref()).is_some());
+    let mocks_options = http_routes().any(|route| route.http_method == HttpMethods::OPTIONS);
+
+    has_cors && !mocks_options
+}
+
+/// Serves the `ANY` and custom verb routes of a path, which axum can't route
+/// by method.
+async fn dispatch(
+    routes: Arc<Vec<Arc<CompiledRoute>>>,
+    store: StateStore,
+    config: Arc<ServerConfig>,
+    request: Parts,
+) -> Response<Body> {
+    let route = routes
+        .iter()
+        .find(|route| route.app.http_method.to_string() == request.method.as_str())
+        .or_else(|| {
+            routes
+                .iter()
+                .find(|route| route.app.http_method == HttpMethods::ANY)
+        });
+
+    match route {
+        Some(route) => handler(route.clone(), store, config, request).await,
+        None => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+    }
+}
+
+/// Answers a preflight with the CORS settings of the route the browser is
+/// about to call.
+async fn preflight(
+    routes: Vec<ApplicationRequirements>,
+    config: Arc<ServerConfig>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let requested = headers
+        .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+        .and_then(|method| method.to_str().ok());
+    let route = requested
+        .and_then(|method| {
+            routes
+                .iter()
+                .find(|route| route.http_method.to_string().eq_ignore_ascii_case(method))
+                .or_else(|| {
+                    routes
+                        .iter()
+                        .find(|route| route.http_method == HttpMethods::ANY)
+                })
+        })
+        .unwrap_or(&routes[0]);
+    let mut methods: Vec<String> = Vec::new();
+    for route in &routes {
+        let route_methods = match &route.http_method {
+            HttpMethods::ANY => ["GET", "POST", "PUT", "PATCH", "DELETE"]
+                .map(str::to_string)
+                .to_vec(),
+            method => vec![method.to_string()],
+        };
+        for method in route_methods {
+            if !methods.contains(&method) {
+                methods.push(method);
+            }
+        }
+    }
+
+    let mut response = match CorsSettings::resolve(config.cors.as_ref(), route.cors.as_ref()) {
+        Some(settings) => settings.preflight(&headers, &methods),
+        None => Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .header(header::ALLOW, methods.join(", "))
+            .body(Body::empty())
+            .unwrap(),
+    };
+    response
+        .extensions_mut()
+        .insert(MatchedRoute(route.path.clone()));
+    response
+}
+
+async fn websocket_handler(
+    route: Arc<CompiledRoute>,
+    store: StateStore,
+    config: Arc<ServerConfig>,
+    upgrade: WebSocketUpgrade,
+) -> Response<Body> {
+    store.lock().record_hit(&route.key);
+
+    let script = route.app.websocket.clone().unwrap_or_default();
+    let jitter = config.jitter.clone();
+    let mut response = upgrade.on_upgrade(move |socket| script.run(socket, jitter));
+    response
+        .extensions_mut()
+        .insert(MatchedRoute(route.app.path.clone()));
+    response
+}
+
+async fn handler(
+    route: Arc<CompiledRoute>,
+    store: StateStore,
+    config: Arc<ServerConfig>,
+    request: Parts,
+) -> Response<Body> {
+    let app = &route.app;
+    if let Some(subject) = &app.client_subject {
+        let policy = config.client_auth.clone().unwrap_or_default();
+        if let Err(reason) =
+            policy.check_subject(request.extensions.get::<ClientIdentity>(), subject)
+        {
+            return policy.reject(reason);
+        }
+    }
+
+    store.lock().record_hit(&route.key);
+
+    let mut response = if route.fixed {
+        route.fixed_response()
+    } else {
+        match respond(&route, &config, &request).await {
+            Ok(response) => response,
+            Err(response) => return response,
+        }
+    };
+
+    let cors = CorsSettings::resolve(config.cors.as_ref(), app.cors.as_ref());
+    let cors_headers = cors
+        .zip(request.headers.get(header::ORIGIN))
+        .and_then(|(cors, origin)| cors.response_headers(origin));
+    for (name, value) in cors_headers.iter().flatten() {
+        response.headers_mut().append(name, value.clone());
+    }
+
+    response
+}
+
+/// The response of a route that isn't `fixed`: delayed, throttled, broken,
+/// streamed, templated or read from a file. Errors are responses too, sent
+/// without the CORS headers.
+async fn respond(
+    route: &CompiledRoute,
+    config: &ServerConfig,
+    request: &Parts,
+) -> Result<Response<Body>, Response<Body>> {
+    let app = &route.app;
+    app.try_sleep(&config.jitter).await;
+
+    let mut status = route.status;
+    let mut response = Response::builder().extension(MatchedRoute(app.path.clone()));
+
+    let cache = config.file_cache.as_deref();
+    let mut file = match &app.http_response_path {
+        Some(path) => {
+            match FileBody::open(Path::new(path), cache, |text| render_body(text, request)).await {
+                Ok(file) => Some(file),
+                Err(err) => return Err(unreadable_file(path, &err)),
+            }
+        }
+        None => None,
+    };
+
+    // Lets clients resume downloads and seek through media. Faults and
+    // error statuses always send the whole body.
+    if let Some(file) = file
+        .as_mut()
+        .filter(|_| status == StatusCode::OK && app.fault.is_none())
+    {
+        response = response.header(header::ACCEPT_RANGES, "bytes");
+        let length = file.len();
+        let range = request
+            .headers
+            .get(header::RANGE)
+            .and_then(|range| range.to_str().ok());
+        match range.map_or(RangeRequest::Whole, |range| parse_range(range, length)) {
+            RangeRequest::Whole => {}
+            RangeRequest::Partial(range) => {
+                file.slice(range);
+                status = StatusCode::PARTIAL_CONTENT;
+                response = response.header(
+                    header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{length}", range.start, range.end),
+                );
+            }
+            RangeRequest::Unsatisfiable => {
+                *file = FileBody::Loaded(Bytes::new());
+                status = StatusCode::RANGE_NOT_SATISFIABLE;
+                response = response.header(header::CONTENT_RANGE, format!("bytes */{length}"));
+            }
+        }
+    }
+
+    let unreadable =
+        |err| unreadable_file(app.http_response_path.as_deref().unwrap_or_default(), &err);
+    let mut on_disk = None;
+    let body = match file {
+        Some(file) if file.is_on_disk() && app.fault.is_none() => {
+            on_disk = Some(file);
+            Bytes::new()
+        }
+        Some(file) => file.into_bytes().await.map_err(unreadable)?,
+        None if route.templated => Bytes::from(render_body(
+            app.http_response_body.as_deref().unwrap_or_default(),
+            request,
+        )),
+        None => route.body.clone(),
+    };
+
+    let bandwidth = app.throttle.or(config.throttle);
+    let first_byte = app.first_byte.as_ref().or(config.first_byte.as_ref());
+    response = response.status(status);
+
+    let body = match (app.fault, &app.stream, on_disk, bandwidth, first_byte) {
+        (Some(fault), ..) => {
+            let control = request.extensions.get::<ConnectionControl>();
+            match fault::inject(fault, control, request.version, status, body).await {
+                Injected::Truncated(body, length) => {
+                    response = response.header(header::CONTENT_LENGTH, length);
+                    body
+                }
+                Injected::Broken => return Err(response.body(Body::empty()).unwrap()),
+            }
+        }
+        (None, Some(stream), ..) => {
+            // Keeps proxies from holding events back until the response ends.
+            response = response.header(header::CACHE_CONTROL, "no-cache");
+            let control = request.extensions.get::<ConnectionControl>().cloned();
+            stream.body(config.jitter.clone(), control)
+        }
+        (None, None, Some(file), bandwidth, first_byte) => {
+            // Streamed bodies have no length of their own.
+            response = response.header(header::CONTENT_LENGTH, file.len());
+            let chunks = file.into_stream().await.map_err(unreadable)?;
+            match (bandwidth, first_byte) {
+                (None, None) => Body::from_stream(chunks),
+                _ => {
+                    let first_byte =
+                        first_byte.map_or(Duration::ZERO, |delay| config.jitter.sample(delay));
+                    throttle::slow_stream(chunks, bandwidth, first_byte)
+                }
+            }
+        }
+        (None, None, None, None, None) => Body::from(body),
+        _ => {
+            // Sent up front so clients can show progress while the body trickles in.
+            response = response.header(header::CONTENT_LENGTH, body.len());
+            let first_byte = first_byte.map_or(Duration::ZERO, |delay| config.jitter.sample(delay));
+            throttle::slow_body(body, bandwidth, first_byte)
+        }
+    };
+
+    let mut response = response.body(body).unwrap();
+    for (name, value) in &route.headers {
+        response.headers_mut().append(name, value.clone());
+    }
+    Ok(response)
+}
+
+/// A 500 rather than a dropped connection when the `File=` of a route is missing.
+fn unreadable_file(path: &str, err: &std::io::Error) -> Response<Body> {
+    eprintln!("Failed to read {path}: {err}");
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to read {path}: {err}"),
+    )
+        .into_response()
+}
+
+/// Fills the `{{name}}` placeholders of a body with what the path captured,
+/// then with the request's method, path, query and uri.
+fn render_body(body: &str, request: &Parts) -> String {
+    let captures = request.extensions.get::<PathCaptures>();
+    template::render(body, |name| {
+        captures
+            .and_then(|captures| captures.get(name))
+            .map(str::to_string)
+            .or_else(|| template::request_value(name, request))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::ServerState;
+    use axum::body::to_bytes;
+    use std::fs;
+
+    #[tokio::test]
+    async fn mocks_take_precedence_over_the_proxy() {
+        let upstream = Router::new().fallback(|| async { "from upstream" });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, upstream).await });
+
+        let mock = ApplicationRequirements {
+            path: "/api/user/:id".to_string(),
+            http_method: HttpMethods::GET,
+            http_response_status: 200,
+            http_response_body: Some("from mock".to_string()),
+            ..ApplicationRequirements::default()
+        };
+        let fixtures = ServerState {
+            routes: vec![mock],
+            ..ServerState::default()
+        };
+        let config = ServerConfig {
+            proxy: Some(Proxy::new(&format!("http://{address}")).unwrap()),
+            ..ServerConfig::default()
+        };
+        let router =
+            MockServer::new(StateStore::open(fixtures, None, false).unwrap(), config).into_router();
+
+        for (uri, expected) in [
+            ("/api/user/1", "from mock"),
+            ("/api/orders", "from upstream"),
+        ] {
+            let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            let response = router.clone().oneshot(request).await.unwrap();
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            assert_eq!(body, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn preflights_are_answered_for_every_mocked_path() {
+        let route = |method| ApplicationRequirements {
+            path: "/api/users".to_string(),
+            http_method: method,
+            http_response_status: 200,
+            ..ApplicationRequirements::default()
+        };
+        let fixtures = ServerState {
+            routes: vec![route(HttpMethods::GET), route(HttpMethods::POST)],
+            ..ServerState::default()
+        };
+        let config = ServerConfig {
+            cors: Some(CorsSettings {
+                origins: vec!["http://localhost:3000".to_string()],
+                max_age: Some(600),
+                ..CorsSettings::default()
+            }),
+            ..ServerConfig::default()
+        };
+        let router =
+            MockServer::new(StateStore::open(fixtures, None, false).unwrap(), config).into_router();
+
+        let preflight = Request::builder()
+            .method("OPTIONS")
+            .uri("/api/users")
+            .header("Origin", "http://localhost:3000")
+            .header("Access-Control-Request-Method", "POST")
+            .header("Access-Control-Request-Headers", "content-type")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(preflight).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let headers = response.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://localhost:3000"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "GET, POST");
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_HEADERS],
+            "content-type"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_MAX_AGE], "600");
+
+        let call = Request::builder()
+            .uri("/api/users")
+            .header("Origin", "http://localhost:3000")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.clone().oneshot(call).await.unwrap();
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://localhost:3000"
+        );
+
+        let stranger = Request::builder()
+            .method("OPTIONS")
+            .uri("/api/users")
+            .header("Origin", "http://evil.example")
+            .header("Access-Control-Request-Method", "GET")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            router.oneshot(stranger).await.unwrap().status(),
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn websocket_routes_share_their_path_with_http_routes() {
+        use futures_util::StreamExt;
+
+        let chat = ApplicationRequirements {
+            path: "/ws/chat".to_string(),
+            http_method: HttpMethods::WS,
+            http_response_status: 101,
+            websocket: Some(crate::websocket::WebSocketScript {
+                on_connect: vec!["welcome".parse().unwrap()],
+                ..Default::default()
+            }),
+            ..ApplicationRequirements::default()
+        };
+        let history = ApplicationRequirements {
+            path: "/ws/chat".to_string(),
+            http_method: HttpMethods::GET,
+            http_response_status: 200,
+            http_response_body: Some("history".to_string()),
+            ..ApplicationRequirements::default()
+        };
+        let fixtures = ServerState {
+            routes: vec![chat, history],
+            ..ServerState::default()
+        };
+        let store = StateStore::open(fixtures, None, false).unwrap();
+        let router = MockServer::new(store.clone(), ServerConfig::default()).into_router();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{address}/ws/chat"))
+            .await
+            .unwrap();
+        assert_eq!(
+            client.next().await.unwrap().unwrap().into_text().unwrap(),
+            "welcome"
+        );
+
+        let plain = crate::client::new()
+            .get(format!("http://{address}/ws/chat").parse().unwrap())
+            .await
+            .unwrap();
+        let body = to_bytes(Body::new(plain.into_body()), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "history");
+
+        let journal = store.lock().journal.entries.clone();
+        assert_eq!(journal.len(), 2);
+        assert_eq!(journal[0].status, 101);
+        assert_eq!(journal[0].matched_route.as_deref(), Some("/ws/chat"));
+    }
+
+    #[tokio::test]
+    async fn every_method_is_routed() {
+        let route = |method: &str, body: &str| ApplicationRequirements {
+            path: "/dav/file".to_string(),
+            http_method: method.parse().unwrap(),
+            http_response_status: 200,
+            http_response_body: Some(body.to_string()),
+            ..ApplicationRequirements::default()
+        };
+        let fixtures = ServerState {
+            routes: vec![
+                route("GET", "get"),
+                route("PROPFIND", "propfind"),
+                route("ANY", "any"),
+                route("TRACE", "trace"),
+                route("OPTIONS", "options"),
+            ],
+            ..ServerState::default()
+        };
+        let config = ServerConfig {
+            cors: Some(CorsSettings::default()),
+            ..ServerConfig::default()
+        };
+        let router =
+            MockServer::new(StateStore::open(fixtures, None, false).unwrap(), config).into_router();
+
+        let call = |method: &str| {
+            let request = Request::builder()
+                .method(method)
+                .uri("/dav/file")
+                .body(Body::empty())
+                .unwrap();
+            let router = router.clone();
+            async move {
+                let response = router.oneshot(request).await.unwrap();
+                let status = response.status();
+                (
+                    status,
+                    to_bytes(response.into_body(), usize::MAX).await.unwrap(),
+                )
+            }
+        };
+
+        assert_eq!(call("GET").await, (StatusCode::OK, Bytes::from("get")));
+        assert_eq!(call("HEAD").await, (StatusCode::OK, Bytes::new()));
+        assert_eq!(
+            call("PROPFIND").await,
+            (StatusCode::OK, Bytes::from("propfind"))
+        );
+        assert_eq!(call("MKCOL").await, (StatusCode::OK, Bytes::from("any")));
+        assert_eq!(call("DELETE").await, (StatusCode::OK, Bytes::from("any")));
+        assert_eq!(call("TRACE").await, (StatusCode::OK, Bytes::from("trace")));
+        assert_eq!(
+            call("OPTIONS").await,
+            (StatusCode::OK, Bytes::from("options"))
+        );
+    }
+
+    #[tokio::test]
+    async fn methods_of_a_path_are_served_together() {
+        let route = |method: &str, status| ApplicationRequirements {
+            path: "/users".to_string(),
+            http_method: method.parse().unwrap(),
+            http_response_status: status,
+            ..ApplicationRequirements::default()
+        };
+        let fixtures = ServerState {
+            // The second GET is a duplicate and is ignored instead of panicking.
+            routes: vec![
+                route("GET", 200),
+                route("POST", 201),
+                route("WS", 101),
+                route("GET", 500),
+            ],
+            ..ServerState::default()
+        };
+        let router = MockServer::new(
+            StateStore::open(fixtures, None, false).unwrap(),
+            ServerConfig::default(),
+        )
+        .into_router();
+
+        for (method, expected) in [
+            ("GET", StatusCode::OK),
+            ("POST", StatusCode::CREATED),
+            ("PUT", StatusCode::METHOD_NOT_ALLOWED),
+        ] {
+            let request = Request::builder()
+                .method(method)
+                .uri("/users")
+                .body(Body::empty())
+                .unwrap();
+            assert_eq!(
+                router.clone().oneshot(request).await.unwrap().status(),
+                expected
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn the_most_specific_path_pattern_answers() {
+        let route = |method: &str, path: &str, body: &str| ApplicationRequirements {
+            path: path.to_string(),
+            http_method: method.parse().unwrap(),
+            http_response_status: 200,
+            http_response_body: Some(body.to_string()),
+            ..ApplicationRequirements::default()
+        };
+        let fixtures = ServerState {
+            routes: vec![
+                route("GET", "/users/*rest", "rest {{rest}}"),
+                route("GET", "/users/:name", "name {{name}}"),
+                route(
+                    "GET",
+                    r"/users/:id(\d+)",
+                    "id {{id}} via {{method}} {{path}}",
+                ),
+                route("POST", "/users/new", "created"),
+            ],
+            ..ServerState::default()
+        };
+        let router = MockServer::new(
+            StateStore::open(fixtures, None, false).unwrap(),
+            ServerConfig::default(),
+        )
+        .into_router();
+
+        let call = |method: &str, uri: &str| {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+            let router = router.clone();
+            async move {
+                let response = router.oneshot(request).await.unwrap();
+                let status = response.status();
+                (
+                    status,
+                    to_bytes(response.into_body(), usize::MAX).await.unwrap(),
+                )
+            }
+        };
+
+        assert_eq!(
+            call("GET", "/users/123").await,
+            (StatusCode::OK, Bytes::from("id 123 via GET /users/123"))
+        );
+        assert_eq!(
+            call("GET", "/users/abc").await,
+            (StatusCode::OK, Bytes::from("name abc"))
+        );
+        assert_eq!(
+            call("GET", "/users/abc/posts/1").await,
+            (StatusCode::OK, Bytes::from("rest abc/posts/1"))
+        );
+        assert_eq!(
+            call("POST", "/users/new").await,
+            (StatusCode::OK, Bytes::from("created"))
+        );
+        // `/users/new` doesn't serve GET, the patterns after it do.
+        assert_eq!(
+            call("GET", "/users/new").await,
+            (StatusCode::OK, Bytes::from("name new"))
+        );
+        assert_eq!(
+            call("DELETE", "/users/new").await.0,
+            StatusCode::METHOD_NOT_ALLOWED
+        );
+        assert_eq!(call("GET", "/posts").await.0, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn priorities_override_specificity() {
+        let route = |method: &str, path: &str, priority, body: &str| ApplicationRequirements {
+            path: path.to_string(),
+            http_method: method.parse().unwrap(),
+            http_response_status: 200,
+            http_response_body: Some(body.to_string()),
+            priority,
+            ..ApplicationRequirements::default()
+        };
+        let routes = vec![
+            route("GET", "/users/:id", None, "param"),
+            route("GET", "/users/me", None, "me"),
+            route("GET", "/users/*rest", Some(5), "maintenance"),
+            route("POST", "/users/:id", Some(-1), "update"),
+            route("GET", "/users/:other", None, "shadowed"),
+        ];
+
+        let order: Vec<String> = match_order(&routes)
+            .iter()
+            .map(|route| route.route_key())
+            .collect();
+        assert_eq!(
+            order,
+            [
+                "GET /users/*rest",
+                "GET /users/me",
+                "GET /users/:id",
+                "GET /users/:other",
+                "POST /users/:id"
+            ]
+        );
+
+        let fixtures = ServerState {
+            routes,
+            ..ServerState::default()
+        };
+        let router = MockServer::new(
+            StateStore::open(fixtures, None, false).unwrap(),
+            ServerConfig::default(),
+        )
+        .into_router();
+        let call = |method: &str, uri: &str| {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap();
+            let router = router.clone();
+            async move {
+                to_bytes(
+                    router.oneshot(request).await.unwrap().into_body(),
+                    usize::MAX,
+                )
+                .await
+                .unwrap()
+            }
+        };
+
+        assert_eq!(call("GET", "/users/me").await, "maintenance");
+        assert_eq!(call("GET", "/users/42").await, "maintenance");
+        // The catch-all doesn't serve POST, so its priority doesn't matter.
+        assert_eq!(call("POST", "/users/42").await, "update");
+    }
+
+    #[tokio::test]
+    async fn files_are_served_as_bytes_with_their_content_type() {
+        let directory =
+            std::env::temp_dir().join(format!("testroute-files-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let png = [0x89, b'P', b'N', b'G', 0xff, 0x00, 0xfe];
+        fs::write(directory.join("avatar.png"), png).unwrap();
+        fs::write(directory.join("user.json"), r#"{"id": "{{id}}"}"#).unwrap();
+
+        let route = |path: &str, file: &str, headers: &[(&str, &str)]| ApplicationRequirements {
+            path: path.to_string(),
+            http_response_status: 200,
+            http_response_path: Some(directory.join(file).to_string_lossy().into_owned()),
+            http_response_headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            ..ApplicationRequirements::default()
+        };
+        let fixtures = ServerState {
+            routes: vec![
+                route("/avatar", "avatar.png", &[]),
+                route("/users/:id", "user.json", &[]),
+                route(
+                    "/download",
+                    "avatar.png",
+                    &[("Content-Type", "application/octet-stream")],
+                ),
+            ],
+            ..ServerState::default()
+        };
+        let router = MockServer::new(
+            StateStore::open(fixtures, None, false).unwrap(),
+            ServerConfig::default(),
+        )
+        .into_router();
+        let call = |uri: &str| {
+            let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            let router = router.clone();
+            async move {
+                let response = router.oneshot(request).await.unwrap();
+                let content_type = response
+                    .headers()
+                    .get(header::CONTENT_TYPE)
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .to_string();
+                (
+                    content_type,
+                    to_bytes(response.into_body(), usize::MAX).await.unwrap(),
+                )
+            }
+        };
+
+        assert_eq!(
+            call("/avatar").await,
+            ("image/png".to_string(), Bytes::from(png.to_vec()))
+        );
+        assert_eq!(
+            call("/users/7").await,
+            (
+                "application/json".to_string(),
+                Bytes::from(r#"{"id": "7"}"#)
+            )
+        );
+        assert_eq!(call("/download").await.0, "application/octet-stream");
+
+        let ranged = |range: &str| {
+            let request = Request::builder()
+                .uri("/avatar")
+                .header(header::RANGE, range)
+                .body(Body::empty())
+                .unwrap();
+            let router = router.clone();
+            async move {
+                let response = router.oneshot(request).await.unwrap();
+                let content_range = response
+                    .headers()
+                    .get(header::CONTENT_RANGE)
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .to_string();
+                (
+                    response.status(),
+                    content_range,
+                    to_bytes(response.into_body(), usize::MAX).await.unwrap(),
+                )
+            }
+        };
+        assert_eq!(
+            ranged("bytes=1-3").await,
+            (
+                StatusCode::PARTIAL_CONTENT,
+                "bytes 1-3/7".to_string(),
+                Bytes::from(png[1..=3].to_vec())
+            )
+        );
+        assert_eq!(
+            ranged("bytes=7-").await,
+            (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                "bytes */7".to_string(),
+                Bytes::new()
+            )
+        );
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
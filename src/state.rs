@@ -0,0 +1,313 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+    app_requirements::ApplicationRequirements,
+    journal::{Journal, JournalEntry},
+};
+
+#[derive(Error, Debug)]
+pub enum StateError {
+    #[error("Could not access the state file: {0}")]
+    Io(#[from] io::Error),
+    #[error("Invalid state file: {0}")]
+    InvalidFormat(#[from] serde_json::Error),
+}
+
+pub const DEFAULT_JOURNAL_LIMIT: usize = 1000;
+
+/// Everything the server accumulates while running, as written to the state file.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ServerState {
+    /// Routes currently served, starting from the imported or prompted ones.
+    #[serde(default)]
+    pub routes: Vec<ApplicationRequirements>,
+    /// The imported or prompted routes `routes` started from, telling the
+    /// changes made through the admin API apart from edits to the `.http` file.
+    #[serde(default)]
+    pub imported: Vec<ApplicationRequirements>,
+    /// Requests served per route, keyed by `METHOD /path`.
+    #[serde(default)]
+    pub hits: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub journal: Journal,
+}
+
+impl ServerState {
+    pub fn load(path: &Path) -> Result<Option<Self>, StateError> {
+        match fs::read_to_string(path) {
+            Ok(content) => Ok(Some(serde_json::from_str(&content)?)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), StateError> {
+        // Write next to the target and rename, so a crash mid-write never
+        // leaves a truncated snapshot behind.
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(tmp, path)?;
+
+        Ok(())
+    }
+
+    pub fn record_hit(&mut self, route: &str) {
+        // Only the first hit of a route allocates its key.
+        match self.hits.get_mut(route) {
+            Some(hits) => *hits += 1,
+            None => {
+                self.hits.insert(route.to_string(), 1);
+            }
+        }
+    }
+
+    /// Replays the route changes made through the admin API since `imported`
+    /// was saved on top of the routes imported now, returning how many there were.
+    fn rebase_routes(&mut self, imported: &[ApplicationRequirements]) -> usize {
+        let previous: HashMap<String, &ApplicationRequirements> = self
+            .imported
+            .iter()
+            .map(|route| (route.route_key(), route))
+            .collect();
+        let removed: Vec<String> = previous
+            .keys()
+            .filter(|key| !self.routes.iter().any(|route| route.route_key() == **key))
+            .cloned()
+            .collect();
+        let changed: Vec<&ApplicationRequirements> = self
+            .routes
+            .iter()
+            .filter(|route| previous.get(&route.route_key()) != Some(route))
+            .collect();
+
+        let mut routes: Vec<ApplicationRequirements> = imported
+            .iter()
+            .filter(|route| !removed.contains(&route.route_key()))
+            .cloned()
+            .collect();
+        for route in &changed {
+            match routes
+                .iter_mut()
+                .find(|existing| existing.route_key() == route.route_key())
+            {
+                Some(existing) => *existing = (*route).clone(),
+                None => routes.push((*route).clone()),
+            }
+        }
+
+        let changes = removed.len() + changed.len();
+        self.routes = routes;
+        self.imported = imported.to_vec();
+        changes
+    }
+}
+
+/// Shared handle to the server state, optionally backed by a state file.
+#[derive(Debug, Clone)]
+pub struct StateStore {
+    inner: Arc<Mutex<ServerState>>,
+    initial: Arc<ServerState>,
+    file: Option<PathBuf>,
+    journal_limit: usize,
+}
+
+impl StateStore {
+    /// Builds the store from the initial fixtures, restoring the snapshot in
+    /// `file` unless `reset` is set or there is nothing to restore yet. The
+    /// routes are those of the fixtures, with the admin API's changes replayed.
+    pub fn open(
+        mut initial: ServerState,
+        file: Option<PathBuf>,
+        reset: bool,
+    ) -> Result<Self, StateError> {
+        initial.imported = initial.routes.clone();
+        let restored = match &file {
+            Some(path) if !reset => ServerState::load(path)?,
+            _ => None,
+        };
+        let restored = restored.map(|mut state| {
+            let changes = state.rebase_routes(&initial.routes);
+            if changes > 0 {
+                eprintln!("Restored {changes} route change(s) made through the admin API on top of the imported routes");
+            }
+            state
+        });
+
+        Ok(Self {
+            inner: Arc::new(Mutex::new(restored.unwrap_or_else(|| initial.clone()))),
+            initial: Arc::new(initial),
+            file,
+            journal_limit: DEFAULT_JOURNAL_LIMIT,
+        })
+    }
+
+    pub fn with_journal_limit(mut self, limit: usize) -> Self {
+        self.journal_limit = limit;
+        self
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, ServerState> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn snapshot(&self) -> ServerState {
+        self.lock().clone()
+    }
+
+    pub fn reset(&self) -> ServerState {
+        let mut state = self.lock();
+        *state = (*self.initial).clone();
+        state.clone()
+    }
+
+    pub fn record_request(&self, entry: JournalEntry) {
+        self.lock().journal.push(entry, self.journal_limit);
+    }
+
+    /// What the state file keeps: everything but the journal, which can hold
+    /// thousands of request bodies.
+    fn persisted(&self) -> ServerState {
+        let state = self.lock();
+        ServerState {
+            routes: state.routes.clone(),
+            imported: state.imported.clone(),
+            hits: state.hits.clone(),
+            journal: Journal::default(),
+        }
+    }
+
+    /// Writes the state file, off the async workers.
+    pub async fn persist(&self) -> Result<(), StateError> {
+        let Some(path) = self.file.clone() else {
+            return Ok(());
+        };
+
+        let state = self.persisted();
+        tokio::task::spawn_blocking(move || state.save(&path))
+            .await
+            .unwrap_or_else(|err| Err(io::Error::other(err).into()))
+    }
+
+    /// Snapshots the state to disk every `interval` until the runtime shuts down.
+    pub fn spawn_autosave(&self, interval: Duration) {
+        if self.file.is_none() || interval.is_zero() {
+            return;
+        }
+
+        let store = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+
+            loop {
+                ticker.tick().await;
+                if let Err(err) = store.persist().await {
+                    eprintln!("Failed to save state: {err}");
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_state_file(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("testroute-{}-{name}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[tokio::test]
+    async fn state_survives_a_restart() {
+        let path = temp_state_file("restart");
+
+        let store = StateStore::open(ServerState::default(), Some(path.clone()), false).unwrap();
+        store.lock().record_hit("GET /foo");
+        store.lock().record_hit("GET /foo");
+        store.persist().await.unwrap();
+
+        let restored = StateStore::open(ServerState::default(), Some(path.clone()), false).unwrap();
+        assert_eq!(restored.snapshot().hits.get("GET /foo"), Some(&2));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reset_flag_ignores_the_snapshot() {
+        let path = temp_state_file("reset-flag");
+
+        let mut saved = ServerState::default();
+        saved.record_hit("POST /bar");
+        saved.save(&path).unwrap();
+
+        let store = StateStore::open(ServerState::default(), Some(path.clone()), true).unwrap();
+        assert_eq!(store.snapshot(), ServerState::default());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn admin_route_changes_are_replayed_on_the_import() {
+        let path = temp_state_file("rebase");
+        let route = |path: &str, status| ApplicationRequirements {
+            path: path.to_string(),
+            http_response_status: status,
+            ..ApplicationRequirements::default()
+        };
+        let import = |routes| ServerState {
+            routes,
+            ..ServerState::default()
+        };
+
+        let store = StateStore::open(
+            import(vec![route("/a", 200), route("/b", 200)]),
+            Some(path.clone()),
+            false,
+        )
+        .unwrap();
+        // Through the admin API: /a changed, /b removed, /c added.
+        store.lock().routes = vec![route("/a", 500), route("/c", 201)];
+        store.persist().await.unwrap();
+
+        // Meanwhile, the `.http` file changed /b and got /d.
+        let restored = StateStore::open(
+            import(vec![route("/a", 200), route("/b", 204), route("/d", 200)]),
+            Some(path.clone()),
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            restored.snapshot().routes,
+            [route("/a", 500), route("/d", 200), route("/c", 201)]
+        );
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reset_restores_the_initial_fixtures() {
+        let mut initial = ServerState::default();
+        initial.record_hit("GET /foo");
+
+        let store = StateStore::open(initial.clone(), None, false).unwrap();
+        store.lock().record_hit("GET /foo");
+        assert_ne!(store.snapshot(), initial);
+
+        assert_eq!(store.reset(), initial);
+        assert_eq!(store.snapshot(), initial);
+    }
+}
@@ -0,0 +1,89 @@
+use axum::http::request::Parts;
+
+use crate::mtls::ClientIdentity;
+
+/// Fills the `{{name}}` placeholders of `template` with what `lookup` gives
+/// for them. Placeholders `lookup` knows nothing about are left as written.
+pub fn render(template: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(length) = rest[start..].find("}}") else {
+            break;
+        };
+        let placeholder = &rest[start..start + length + 2];
+
+        rendered.push_str(&rest[..start]);
+        match lookup(placeholder[2..placeholder.len() - 2].trim()) {
+            Some(value) => rendered.push_str(&value),
+            None => rendered.push_str(placeholder),
+        }
+        rest = &rest[start + length + 2..];
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+/// The values of `{{method}}`, `{{path}}`, `{{query}}`, `{{uri}}` and
+/// `{{client_subject}}`, empty without a client certificate.
+pub fn request_value(name: &str, request: &Parts) -> Option<String> {
+    match name {
+        "method" => Some(request.method.to_string()),
+        "path" => Some(request.uri.path().to_string()),
+        "query" => Some(request.uri.query().unwrap_or_default().to_string()),
+        "uri" => Some(request.uri.to_string()),
+        "client_subject" => Some(
+            request
+                .extensions
+                .get::<ClientIdentity>()
+                .and_then(ClientIdentity::subject)
+                .map(|subject| subject.text.clone())
+                .unwrap_or_default(),
+        ),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_are_filled_or_left_alone() {
+        let lookup = |name: &str| (name == "path").then(|| "/users".to_string());
+
+        assert_eq!(
+            render("No route for {{ path }}", lookup),
+            "No route for /users"
+        );
+        assert_eq!(render("{{path}}{{path}}", lookup), "/users/users");
+        assert_eq!(
+            render("{{unknown}} and {{path", lookup),
+            "{{unknown}} and {{path"
+        );
+        assert_eq!(render(r#"{"a": {"b": 1}}"#, lookup), r#"{"a": {"b": 1}}"#);
+    }
+
+    #[test]
+    fn client_subject_comes_from_the_certificate() {
+        let (mut request, _) = axum::http::Request::new(()).into_parts();
+        assert_eq!(
+            request_value("client_subject", &request).as_deref(),
+            Some("")
+        );
+
+        let subject = crate::mtls::Subject {
+            text: "CN=alice".to_string(),
+            attributes: vec![("CN".to_string(), "alice".to_string())],
+        };
+        request
+            .extensions
+            .insert(ClientIdentity::Trusted { subject });
+        assert_eq!(
+            request_value("client_subject", &request).as_deref(),
+            Some("CN=alice")
+        );
+    }
+}
@@ -0,0 +1,159 @@
+use std::{fmt, io, str::FromStr, time::Duration};
+
+use axum::body::{Body, Bytes};
+use futures_util::{stream, Stream, StreamExt};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+#[error("Invalid throughput, expected bytes per second e.g. `512`, `16kb` or `1.5mb`: {0}")]
+pub struct InvalidBandwidth(pub String);
+
+/// How often a throttled body sends a chunk.
+const TICK: Duration = Duration::from_millis(100);
+
+/// A throughput limit in bytes per second, written as `512`, `16kb` or `1.5mb`
+/// (an optional `/s` is accepted too).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Bandwidth(pub u64);
+
+impl Bandwidth {
+    /// Bytes sent every `TICK`, so the body trickles in rather than arriving
+    /// in one burst per second.
+    fn chunk_size(self) -> usize {
+        let per_tick = self.0 as f64 * TICK.as_secs_f64();
+        (per_tick.ceil() as usize).max(1)
+    }
+
+    fn time_to_send(self, bytes: usize) -> Duration {
+        Duration::from_secs_f64(bytes as f64 / self.0 as f64)
+    }
+}
+
+impl FromStr for Bandwidth {
+    type Err = InvalidBandwidth;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidBandwidth(value.to_string());
+        let trimmed = value.trim();
+        let trimmed = trimmed
+            .strip_suffix("/s")
+            .unwrap_or(trimmed)
+            .to_ascii_lowercase();
+
+        let split = trimmed
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(trimmed.len());
+        let (amount, unit) = trimmed.split_at(split);
+        let amount: f64 = amount.parse().map_err(|_| invalid())?;
+
+        let multiplier = match unit.trim() {
+            "" | "b" => 1.0,
+            "kb" => 1024.0,
+            "mb" => 1024.0 * 1024.0,
+            _ => return Err(invalid()),
+        };
+
+        match (amount * multiplier).round() {
+            bytes if bytes >= 1.0 && bytes <= u64::MAX as f64 => Ok(Bandwidth(bytes as u64)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Bandwidth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            bytes if bytes.is_multiple_of(1024 * 1024) => write!(f, "{}mb", bytes / (1024 * 1024)),
+            bytes if bytes.is_multiple_of(1024) => write!(f, "{}kb", bytes / 1024),
+            bytes => write!(f, "{bytes}"),
+        }
+    }
+}
+
+impl Serialize for Bandwidth {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Accepts `"16kb"` as well as a plain number of bytes per second.
+impl<'de> Deserialize<'de> for Bandwidth {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::String(value) => value.parse().map_err(serde::de::Error::custom),
+            other => other.to_string().parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// Streams `bytes` after waiting `first_byte`, at most `bandwidth` bytes per second.
+pub fn slow_body(bytes: Bytes, bandwidth: Option<Bandwidth>, first_byte: Duration) -> Body {
+    slow_stream(stream::once(async { Ok(bytes) }), bandwidth, first_byte)
+}
+
+/// Like `slow_body`, for a body arriving in chunks, e.g. read from disk.
+pub fn slow_stream<S>(chunks: S, bandwidth: Option<Bandwidth>, first_byte: Duration) -> Body
+where
+    S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+{
+    let chunk_size = bandwidth.map_or(usize::MAX, Bandwidth::chunk_size);
+
+    let state = (Box::pin(chunks), Bytes::new(), first_byte);
+    let paced = stream::unfold(state, move |(mut chunks, mut rest, wait)| async move {
+        while rest.is_empty() {
+            match chunks.next().await? {
+                Ok(chunk) => rest = chunk,
+                Err(err) => return Some((Err(err), (chunks, rest, wait))),
+            }
+        }
+
+        tokio::time::sleep(wait).await;
+        let chunk = rest.split_to(chunk_size.min(rest.len()));
+        let wait = bandwidth.map_or(Duration::ZERO, |bandwidth| {
+            bandwidth.time_to_send(chunk.len())
+        });
+
+        Some((Ok(chunk), (chunks, rest, wait)))
+    });
+
+    Body::from_stream(paced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::to_bytes;
+    use tokio::time::Instant;
+
+    #[test]
+    fn bandwidths_accept_units() {
+        assert_eq!("512".parse(), Ok(Bandwidth(512)));
+        assert_eq!("16kb".parse(), Ok(Bandwidth(16 * 1024)));
+        assert_eq!("1.5MB/s".parse(), Ok(Bandwidth(1536 * 1024)));
+        assert_eq!(Bandwidth(16 * 1024).to_string(), "16kb");
+
+        for invalid in ["", "0", "fast", "10gb", "-1kb"] {
+            assert!(
+                invalid.parse::<Bandwidth>().is_err(),
+                "{invalid} should be rejected"
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn slow_bodies_trickle_in_at_the_given_rate() {
+        let start = Instant::now();
+        let body = slow_body(
+            Bytes::from(vec![b'x'; 1000]),
+            Some(Bandwidth(500)),
+            Duration::from_millis(300),
+        );
+
+        let bytes = to_bytes(body, usize::MAX).await.unwrap();
+
+        assert_eq!(bytes.len(), 1000);
+        // The first chunk goes out after 300ms, the other 950 bytes take 1.9s more.
+        assert_eq!(start.elapsed(), Duration::from_millis(2200));
+    }
+}
@@ -0,0 +1,309 @@
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+    sync::Arc,
+};
+
+use thiserror::Error;
+use tokio_rustls::rustls::{
+    self,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime},
+    server::VerifierBuilderError,
+    ClientConfig, DigitallySignedStruct, RootCertStore, ServerConfig, SignatureScheme,
+};
+
+use crate::{mtls::ClientCertVerification, serve::HttpVersions};
+
+#[derive(Error, Debug)]
+pub enum TlsError {
+    #[error("Could not read {0}: {1}")]
+    Io(String, io::Error),
+    #[error("No certificate found in {0}")]
+    MissingCertificate(String),
+    #[error("No private key found in {0}")]
+    MissingPrivateKey(String),
+    #[error("Could not generate a self-signed certificate: {0}")]
+    SelfSigned(#[from] rcgen::Error),
+    #[error("Invalid TLS configuration: {0}")]
+    Rustls(#[from] rustls::Error),
+    #[error("Invalid client CA bundle: {0}")]
+    ClientVerifier(#[from] VerifierBuilderError),
+}
+
+/// Names the generated certificate is valid for, besides the `--host` in use.
+const SELF_SIGNED_NAMES: [&str; 3] = ["localhost", "127.0.0.1", "::1"];
+
+pub struct Identity {
+    pub certificates: Vec<CertificateDer<'static>>,
+    pub private_key: PrivateKeyDer<'static>,
+}
+
+impl Identity {
+    pub fn from_pem_files(cert: &Path, key: &Path) -> Result<Self, TlsError> {
+        let certificates = read_certificates(cert)?;
+        let private_key = rustls_pemfile::private_key(&mut open(key)?)
+            .map_err(|err| TlsError::Io(key.display().to_string(), err))?
+            .ok_or_else(|| TlsError::MissingPrivateKey(key.display().to_string()))?;
+
+        Ok(Self {
+            certificates,
+            private_key,
+        })
+    }
+
+    pub fn self_signed(host: &str) -> Result<Self, TlsError> {
+        let mut names: Vec<String> = SELF_SIGNED_NAMES
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        if !names.iter().any(|name| name == host) {
+            names.push(host.to_string());
+        }
+
+        let certified = rcgen::generate_simple_self_signed(names)?;
+
+        Ok(Self {
+            certificates: vec![certified.cert.der().clone()],
+            private_key: PrivateKeyDer::Pkcs8(certified.key_pair.serialize_der().into()),
+        })
+    }
+
+    pub fn server_config(
+        self,
+        versions: HttpVersions,
+        client_auth: Option<Arc<ClientCertVerification>>,
+    ) -> Result<TlsSetup, TlsError> {
+        let builder = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()?;
+        let builder = match &client_auth {
+            Some(verification) => builder.with_client_cert_verifier(verification.clone()),
+            None => builder.with_no_client_auth(),
+        };
+
+        let mut config = builder.with_single_cert(self.certificates, self.private_key)?;
+        config.alpn_protocols = versions.alpn_protocols();
+
+        Ok(TlsSetup {
+            config: Arc::new(config),
+            client_auth,
+        })
+    }
+}
+
+/// Everything `serve::serve` needs to terminate TLS.
+#[derive(Clone)]
+pub struct TlsSetup {
+    pub config: Arc<ServerConfig>,
+    /// Set when clients are asked for a certificate, see `--client-ca`.
+    pub client_auth: Option<Arc<ClientCertVerification>>,
+}
+
+/// What a client connecting to an `https` server trusts, e.g. `testroute verify`.
+pub fn client_config(ca: Option<&Path>, insecure: bool) -> Result<ClientConfig, TlsError> {
+    let provider = Arc::new(ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+
+    let config = if insecure {
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AnyServerCertificate(provider)))
+            .with_no_client_auth()
+    } else {
+        let mut roots = RootCertStore::empty();
+        if let Some(ca) = ca {
+            for certificate in read_certificates(ca)? {
+                roots.add(certificate)?;
+            }
+        }
+        builder.with_root_certificates(roots).with_no_client_auth()
+    };
+
+    Ok(config)
+}
+
+/// Accepts whatever certificate the server presents, e.g. a self-signed one.
+/// Signatures are still checked, so the server must hold the certificate's key.
+#[derive(Debug)]
+struct AnyServerCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AnyServerCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Reads every certificate in a PEM file, failing if there is none.
+pub fn read_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsError> {
+    let certificates = rustls_pemfile::certs(&mut open(path)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| TlsError::Io(path.display().to_string(), err))?;
+    if certificates.is_empty() {
+        return Err(TlsError::MissingCertificate(path.display().to_string()));
+    }
+
+    Ok(certificates)
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, TlsError> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|err| TlsError::Io(path.display().to_string(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serve::serve;
+    use axum::Router;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+    use tokio_rustls::{
+        client::TlsStream,
+        rustls::{pki_types::ServerName, ClientConfig, RootCertStore},
+        TlsConnector,
+    };
+
+    /// Starts a TLS server answering "secure" and connects to it, offering `alpn`.
+    async fn connect(versions: HttpVersions, alpn: &[&[u8]]) -> TlsStream<TcpStream> {
+        let identity = Identity::self_signed("localhost").unwrap();
+        let mut roots = RootCertStore::empty();
+        roots.add(identity.certificates[0].clone()).unwrap();
+        let setup = identity.server_config(versions, None).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let router = Router::new().fallback(|| async { "secure" });
+        tokio::spawn(serve(
+            listener,
+            router,
+            Some(setup),
+            versions,
+            std::future::pending(),
+        ));
+
+        let mut client = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        client.alpn_protocols = alpn.iter().map(|protocol| protocol.to_vec()).collect();
+
+        let stream = TcpStream::connect(address).await.unwrap();
+        TlsConnector::from(Arc::new(client))
+            .connect(ServerName::try_from("localhost").unwrap(), stream)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn serve_answers_over_tls_with_a_self_signed_certificate() {
+        let mut stream = connect(HttpVersions::Any, &[]).await;
+
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("secure"));
+    }
+
+    #[tokio::test]
+    async fn alpn_negotiates_http2_unless_http1_is_forced() {
+        let offered: &[&[u8]] = &[b"h2", b"http/1.1"];
+
+        let stream = connect(HttpVersions::Any, offered).await;
+        assert_eq!(stream.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+
+        let stream = connect(HttpVersions::Http1Only, offered).await;
+        assert_eq!(stream.get_ref().1.alpn_protocol(), Some(&b"http/1.1"[..]));
+    }
+
+    #[tokio::test]
+    async fn clients_accept_self_signed_certificates_only_when_insecure() {
+        let setup = Identity::self_signed("localhost")
+            .unwrap()
+            .server_config(HttpVersions::Any, None)
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let router = Router::new().fallback(|| async { "secure" });
+        tokio::spawn(serve(
+            listener,
+            router,
+            Some(setup),
+            HttpVersions::Any,
+            std::future::pending(),
+        ));
+
+        let send = |insecure| async move {
+            let request =
+                axum::http::Request::get(format!("https://localhost:{}/", address.port()))
+                    .body(axum::body::Body::empty())
+                    .unwrap();
+            let config = client_config(None, insecure).unwrap();
+            crate::client::send_once(request, Some(Arc::new(config))).await
+        };
+
+        let response = send(true).await.unwrap();
+        let body = axum::body::to_bytes(axum::body::Body::new(response.into_body()), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "secure");
+        assert!(send(false).await.unwrap_err().contains("UnknownIssuer"));
+    }
+
+    #[test]
+    fn from_pem_files_reports_missing_files() {
+        let result =
+            Identity::from_pem_files(Path::new("missing-cert.pem"), Path::new("missing-key.pem"));
+        assert!(matches!(result, Err(TlsError::Io(_, _))));
+    }
+}
@@ -1,49 +1,146 @@
-use crate::app_requirements::{ApplicationRequirements, HttpMethods};
+use crate::{
+    app_requirements::{ApplicationRequirements, HttpMethods},
+    cors::{self, CorsSettings},
+    event_stream::EventStream,
+    fallback::Fallback,
+    route_pattern::{InvalidPattern, PathPattern},
+    websocket::WebSocketScript,
+};
 use super::tokens::Token;
+use axum::http::{HeaderName, HeaderValue};
 use thiserror::Error;
-use std::str::FromStr;
+use std::{iter::Peekable, slice::Iter, str::FromStr};
+
+/// Starts the block answering the requests no route matches, e.g. `FALLBACK 404`.
+const FALLBACK: &str = "FALLBACK";
 
 #[derive(Error, Debug)]
 pub enum ParseError {
+    #[error("Invalid token -> [{0}]")]
+    InvalidToken(char),
+    #[error("Unterminated string: \"{0}")]
+    UnterminatedString(String),
     #[error("Missing HTTP method")]
     MissingHttpMethod,
     #[error("Invalid HTTP method: {0}")]
     InvalidHttpMethod(String),
     #[error("Missing path")]
     MissingPath,
+    #[error(transparent)]
+    InvalidPath(#[from] InvalidPattern),
     #[error("Missing HTTP status code")]
     MissingStatusCode,
     #[error("Invalid HTTP status code: {0}")]
     InvalidStatusCode(String),
-    #[error("Invalid delay value: {0}")]
+    #[error("Invalid delay, expected e.g. `Delay=250ms`, `Delay=1.5s` or `Delay=uniform(100ms, 800ms)`: {0}")]
     InvalidDelay(String),
+    #[error("Invalid header, expected `Header=Name: value`: {0}")]
+    InvalidHeader(String),
+    #[error("Missing value for option: {0}")]
+    MissingOptionValue(String),
+    #[error("Invalid value for {0}: {1}")]
+    InvalidOptionValue(String, String),
+    #[error("Unknown option: {0}")]
+    UnknownOption(String),
+    #[error("`{0}=` only applies to `WS` routes")]
+    NotAWebSocket(String),
+    #[error("Route defined twice: {0}")]
+    DuplicateRoute(String),
+    #[error("FALLBACK blocks are only read from the imported .http file")]
+    UnexpectedFallback,
+    #[error("FALLBACK is declared twice")]
+    DuplicateFallback,
+    #[error("Unclosed response body")]
+    UnclosedBody,
+}
+
+/// What a `.http` file declares: its routes and what answers the requests
+/// none of them match.
+#[derive(Debug, Default)]
+pub struct HttpFile {
+    pub routes: Vec<ApplicationRequirements>,
+    pub fallback: Option<Fallback>,
 }
 
+/// Parses routes, refusing `FALLBACK` blocks.
 pub fn parse_requirements(tokens: &[Token]) -> Result<Vec<ApplicationRequirements>, ParseError> {
-    let mut requirements = Vec::new();
-    let mut iter = tokens.iter().peekable();
+    let file = parse_file(tokens)?;
+    if file.fallback.is_some() {
+        return Err(ParseError::UnexpectedFallback);
+    }
 
-    while iter.peek().is_some() {
-        let mut current_tokens = Vec::new();
+    Ok(file.routes)
+}
+
+pub fn parse_file(tokens: &[Token]) -> Result<HttpFile, ParseError> {
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    let mut depth = 0usize;
 
-        // Collect tokens for the current requirement
-        while let Some(token) = iter.next() {
-            current_tokens.push(token);
-            if matches!(token, Token::RightBrace) {
-                break;
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::LeftBrace | Token::LeftBracket => depth += 1,
+            Token::RightBrace | Token::RightBracket => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+
+        // A new requirement starts at every `METHOD /path` outside of a body
+        if index > start && depth == 0 && starts_requirement(tokens, index) {
+            blocks.push(&tokens[start..index]);
+            start = index;
+        }
+    }
+
+    if start < tokens.len() {
+        blocks.push(&tokens[start..]);
+    }
+
+    let mut requirements = Vec::new();
+    let mut fallback = None;
+    for block in blocks {
+        match block.first() {
+            Some(Token::Identifier(keyword)) if keyword == FALLBACK => {
+                if fallback.replace(parse_fallback(block)?).is_some() {
+                    return Err(ParseError::DuplicateFallback);
+                }
             }
+            _ => requirements.push(parse_single_requirement(block)?),
         }
+    }
 
-        // Parse the current requirement
-        let requirement = parse_single_requirement(&current_tokens)?;
-        requirements.push(requirement);
+    for (index, requirement) in requirements.iter().enumerate() {
+        let defined_before = requirements[..index].iter().any(|other| {
+            other.http_method == requirement.http_method && other.path == requirement.path
+        });
+        if defined_before {
+            return Err(ParseError::DuplicateRoute(requirement.route_key()));
+        }
     }
 
-    Ok(requirements)
+    Ok(HttpFile {
+        routes: requirements,
+        fallback,
+    })
+}
+
+fn starts_requirement(tokens: &[Token], index: usize) -> bool {
+    let after_option = index > 0 && matches!(tokens[index - 1], Token::Equal);
+
+    match (&tokens[index], tokens.get(index + 1)) {
+        (Token::Identifier(keyword), _) if keyword == FALLBACK => !after_option,
+        (Token::Identifier(_), Some(Token::Identifier(path))) => {
+            !after_option && path.starts_with('/')
+        }
+        (Token::Identifier(_), Some(Token::Quote)) => {
+            !after_option
+                && matches!(tokens.get(index + 2), Some(Token::Identifier(path)) if path.starts_with('/'))
+        }
+        _ => false,
+    }
 }
 
-fn parse_single_requirement(tokens: &Vec<&Token>) -> Result<ApplicationRequirements, ParseError> {
-    let mut iter = tokens.iter();
+fn parse_single_requirement(tokens: &[Token]) -> Result<ApplicationRequirements, ParseError> {
+    let mut iter = tokens.iter().peekable();
 
     let http_method = match iter.next() {
         Some(Token::Identifier(method)) => HttpMethods::from_str(method)
@@ -51,59 +148,227 @@ fn parse_single_requirement(tokens: &Vec<&Token>) -> Result<ApplicationRequireme
         _ => return Err(ParseError::MissingHttpMethod),
     };
 
-    let path = match iter.next() {
-        Some(Token::Identifier(path)) => path.clone(),
+    // Paths whose regexes hold `{`, `}` or `,` are quoted, e.g. `"/posts/:year(\d{4})"`.
+    let path = match (iter.next(), iter.peek()) {
+        (Some(Token::Identifier(path)), _) => path.clone(),
+        (Some(Token::Quote), Some(Token::Identifier(path))) => {
+            iter.next();
+            iter.next_if_eq(&&Token::Quote);
+            path.clone()
+        }
         _ => return Err(ParseError::MissingPath),
     };
+    PathPattern::parse(&path)?;
 
-    let http_response_status = match iter.next() {
-        Some(Token::Identifier(status)) => status.parse()
-            .map_err(|_| ParseError::InvalidStatusCode(status.clone()))?,
+    // WebSocket routes always answer 101 Switching Protocols, so the status is optional.
+    let is_websocket = http_method == HttpMethods::WS;
+    let is_status = |token: &&Token| matches!(token, Token::Identifier(status) if status.parse::<u16>().is_ok());
+    let http_response_status = match iter.next_if(|token| !is_websocket || is_status(token)) {
+        Some(Token::Identifier(status)) => parse_status(status)?,
+        None if is_websocket => 101,
         _ => return Err(ParseError::MissingStatusCode),
     };
 
-    let mut delay = None;
-    let mut http_response_body = None;
+    let mut requirement = ApplicationRequirements {
+        path,
+        http_method,
+        http_response_status,
+        ..ApplicationRequirements::default()
+    };
 
     while let Some(token) = iter.next() {
         match token {
-            Token::Identifier(ident) if ident == "Delay" => {
-                if let Some(Token::Equal) = iter.next() {
-                    if let Some(Token::Identifier(delay_value)) = iter.next() {
-                        delay = Some(delay_value.parse()
-                            .map_err(|_| ParseError::InvalidDelay(delay_value.clone()))?);
-                    }
-                }
+            Token::Identifier(option) if matches!(iter.peek(), Some(Token::Equal)) => {
+                iter.next();
+                let value = match iter.next_if(|token| matches!(token, Token::Identifier(_))) {
+                    Some(Token::Identifier(value)) => value,
+                    _ => return Err(ParseError::MissingOptionValue(option.clone())),
+                };
+                apply_option(&mut requirement, option, value)?;
             }
-            Token::LeftBrace => {
-                let mut json_content = String::from("{");
-                while let Some(token) = iter.next() {
-                    if matches!(token, Token::RightBrace) {
-                        json_content.push('}');
-                        break;
+            Token::LeftBrace | Token::LeftBracket => {
+                requirement.http_response_body = Some(read_body(token, &mut iter)?);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(requirement)
+}
+
+/// Reads a JSON body starting at `open` up to its matching closing token.
+fn read_body(open: &Token, iter: &mut Peekable<Iter<'_, Token>>) -> Result<String, ParseError> {
+    let mut body = open.to_string();
+    let mut depth = 1;
+    for token in iter.by_ref() {
+        match token {
+            Token::LeftBrace | Token::LeftBracket => depth += 1,
+            Token::RightBrace | Token::RightBracket => depth -= 1,
+            _ => {}
+        }
+        body.push_str(&token.to_string());
+        if depth == 0 {
+            return Ok(body);
+        }
+    }
+
+    Err(ParseError::UnclosedBody)
+}
+
+/// Parses `FALLBACK [status]`, followed by `Header=`, `File=` and
+/// `SuggestRoutes=` options and an optional body.
+fn parse_fallback(tokens: &[Token]) -> Result<Fallback, ParseError> {
+    let mut iter = tokens[1..].iter().peekable();
+    let mut fallback = Fallback::default();
+
+    let is_status = |token: &&Token| matches!(token, Token::Identifier(status) if status.parse::<u16>().is_ok());
+    if let Some(Token::Identifier(status)) = iter.next_if(is_status) {
+        fallback.status = parse_status(status)?;
+    }
+
+    while let Some(token) = iter.next() {
+        match token {
+            Token::Identifier(option) if matches!(iter.peek(), Some(Token::Equal)) => {
+                iter.next();
+                let value = match iter.next_if(|token| matches!(token, Token::Identifier(_))) {
+                    Some(Token::Identifier(value)) => value,
+                    _ => return Err(ParseError::MissingOptionValue(option.clone())),
+                };
+                match option.as_str() {
+                    "Header" => {
+                        let (name, header_value) = parse_header(value)?;
+                        fallback.headers.insert(name, header_value);
+                    }
+                    "File" => fallback.file = Some(value.to_string()),
+                    "SuggestRoutes" => {
+                        fallback.suggest_routes = value.parse().map_err(|_| {
+                            ParseError::InvalidOptionValue(option.to_string(), value.to_string())
+                        })?;
                     }
-                    json_content.push_str(&token.to_string());
+                    _ => return Err(ParseError::UnknownOption(option.to_string())),
                 }
-                http_response_body = Some(json_content);
+            }
+            Token::LeftBrace | Token::LeftBracket => {
+                fallback.body = Some(read_body(token, &mut iter)?)
             }
             _ => {}
         }
     }
 
-    Ok(ApplicationRequirements {
-        path,
-        http_method,
-        http_response_status,
-        http_response_body,
-        http_response_path: None,
-        delay,
-    })
+    Ok(fallback)
+}
+
+/// Parses a status code, which HTTP allows anywhere in 100 to 999.
+fn parse_status(status: &str) -> Result<u16, ParseError> {
+    status
+        .parse()
+        .ok()
+        .filter(|code| (100..1000).contains(code))
+        .ok_or_else(|| ParseError::InvalidStatusCode(status.to_string()))
+}
+
+/// Reads `Name: value`, refusing what can't be sent as a header, e.g. a name with a space.
+pub fn parse_header(value: &str) -> Result<(String, String), ParseError> {
+    let invalid = || ParseError::InvalidHeader(value.to_string());
+    let (name, header_value) = value.split_once(':').ok_or_else(invalid)?;
+    let (name, header_value) = (name.trim(), header_value.trim());
+    HeaderName::try_from(name).map_err(|_| invalid())?;
+    HeaderValue::try_from(header_value).map_err(|_| invalid())?;
+
+    Ok((name.to_string(), header_value.to_string()))
+}
+
+fn apply_option(
+    requirement: &mut ApplicationRequirements,
+    option: &str,
+    value: &str,
+) -> Result<(), ParseError> {
+    match option {
+        "Delay" => {
+            requirement.delay = Some(
+                value
+                    .parse()
+                    .map_err(|_| ParseError::InvalidDelay(value.to_string()))?,
+            );
+        }
+        "Header" => {
+            let (name, header_value) = parse_header(value)?;
+            requirement.http_response_headers.insert(name, header_value);
+        }
+        "File" => {
+            requirement.http_response_path = Some(value.to_string());
+        }
+        "Priority" => {
+            requirement.priority = Some(value.parse().map_err(|_| {
+                ParseError::InvalidOptionValue(option.to_string(), value.to_string())
+            })?);
+        }
+        "Throttle" => {
+            requirement.throttle = Some(value.parse().map_err(|_| {
+                ParseError::InvalidOptionValue(option.to_string(), value.to_string())
+            })?);
+        }
+        "FirstByte" => {
+            requirement.first_byte = Some(value.parse().map_err(|_| {
+                ParseError::InvalidOptionValue(option.to_string(), value.to_string())
+            })?);
+        }
+        "Fault" => {
+            requirement.fault = Some(value.parse().map_err(|_| {
+                ParseError::InvalidOptionValue(option.to_string(), value.to_string())
+            })?);
+        }
+        "ClientCert" => {
+            requirement.client_subject = Some(value.to_string());
+        }
+        "Stream" | "Event" | "Repeat" => {
+            let invalid = || ParseError::InvalidOptionValue(option.to_string(), value.to_string());
+            let stream = requirement.stream.get_or_insert_with(EventStream::default);
+            match option {
+                "Stream" => stream.format = value.parse().map_err(|_| invalid())?,
+                "Event" => stream.events.push(value.parse().map_err(|_| invalid())?),
+                _ => stream.repeat = Some(value.parse().map_err(|_| invalid())?),
+            }
+        }
+        "OnConnect" | "Reply" | "Push" | "Echo" => {
+            if requirement.http_method != HttpMethods::WS {
+                return Err(ParseError::NotAWebSocket(option.to_string()));
+            }
+            let invalid = || ParseError::InvalidOptionValue(option.to_string(), value.to_string());
+            let script = requirement
+                .websocket
+                .get_or_insert_with(WebSocketScript::default);
+            match option {
+                "OnConnect" => script
+                    .on_connect
+                    .push(value.parse().map_err(|_| invalid())?),
+                "Reply" => script.replies.push(value.parse().map_err(|_| invalid())?),
+                "Push" => script.pushes.push(value.parse().map_err(|_| invalid())?),
+                _ => script.echo = value.parse().map_err(|_| invalid())?,
+            }
+        }
+        "CorsOrigin" | "CorsMethods" | "CorsHeaders" | "CorsCredentials" | "CorsMaxAge" => {
+            let invalid = || ParseError::InvalidOptionValue(option.to_string(), value.to_string());
+            let cors = requirement.cors.get_or_insert_with(CorsSettings::default);
+            match option {
+                "CorsOrigin" => cors.origins.extend(cors::split_list(value)),
+                "CorsMethods" => cors.methods = cors::split_list(value),
+                "CorsHeaders" => cors.headers = cors::split_list(value),
+                "CorsCredentials" => cors.credentials = Some(value.parse().map_err(|_| invalid())?),
+                _ => cors.max_age = Some(value.parse().map_err(|_| invalid())?),
+            }
+        }
+        _ => return Err(ParseError::UnknownOption(option.to_string())),
+    }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::app_requirements::HttpMethods;
+    use crate::{app_requirements::HttpMethods, delay::Delay};
+    use std::time::Duration;
 
     #[test]
     fn parse_from_tokens_to_requirements() {
@@ -124,7 +389,8 @@ mod tests {
                 http_response_status: 200,
                 http_response_body: None,
                 http_response_path: None,
-                delay: Some(1)
+                delay: Some(Delay::Fixed(Duration::from_secs(1))),
+                ..ApplicationRequirements::default()
             }]
         );
     }
@@ -132,7 +398,7 @@ mod tests {
     #[test]
     fn parse_with_invalid_http_method() {
         let tokens = vec![
-            Token::Identifier("INVALID".to_string()),
+            Token::Identifier("Get".to_string()),
             Token::Identifier("/api/user/:id".to_string()),
             Token::Identifier("200".to_string()),
         ];
@@ -158,6 +424,19 @@ mod tests {
         ];
         let result = parse_requirements(&tokens);
         assert!(matches!(result, Err(ParseError::InvalidStatusCode(_))));
+
+        for status in ["42", "1000"] {
+            let tokens = vec![
+                Token::Identifier("GET".to_string()),
+                Token::Identifier("/api/user/:id".to_string()),
+                Token::Identifier(status.to_string()),
+            ];
+            let result = parse_requirements(&tokens);
+            assert!(
+                matches!(result, Err(ParseError::InvalidStatusCode(_))),
+                "{status}"
+            );
+        }
     }
 
     #[test]
@@ -197,8 +476,217 @@ mod tests {
                 http_response_status: 201,
                 http_response_body: Some(r#"{"id":123,"name":"John Doe","active":true}"#.to_string()),
                 http_response_path: None,
-                delay: None
+                delay: None,
+                ..ApplicationRequirements::default()
             }]
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn parse_blocks_without_bodies_and_with_options() {
+        let example = r#"
+        GET /api/user/:id 200
+        Header=Content-Type: application/json
+        Header=X-Request-Id: 1234
+
+        GET /api/user/:id/avatar 200
+        File=./avatar.png
+
+        POST /api/user 201
+
+        {
+          "user": { "id": 1, "roles": ["admin", "dev"] }
+        }
+        "#;
+        let tokens = crate::tokenizer::tokens::parse(example.to_string()).unwrap();
+        let result = parse_requirements(&tokens).unwrap();
+
+        assert_eq!(result.len(), 3);
+        assert_eq!(
+            result[0]
+                .http_response_headers
+                .get("Content-Type")
+                .map(String::as_str),
+            Some("application/json")
+        );
+        assert_eq!(
+            result[0]
+                .http_response_headers
+                .get("X-Request-Id")
+                .map(String::as_str),
+            Some("1234")
+        );
+        assert_eq!(result[0].http_response_body, None);
+        assert_eq!(
+            result[1].http_response_path,
+            Some("./avatar.png".to_string())
+        );
+        assert_eq!(
+            result[2].http_response_body,
+            Some(r#"{"user":{"id":1,"roles":["admin","dev"]}}"#.to_string())
+        );
+    }
+
+    #[test]
+    fn parse_streams_with_scripted_events() {
+        let example = r#"
+        GET /api/feed 200
+        Stream=sse
+        Repeat=2
+        Event=id=1; event=tick; data={"n": 1}
+        Event=delay=500ms; data=done
+        "#;
+        let tokens = crate::tokenizer::tokens::parse(example.to_string()).unwrap();
+        let result = parse_requirements(&tokens).unwrap();
+
+        let stream = result[0].stream.as_ref().unwrap();
+        assert_eq!(stream.format, crate::event_stream::StreamFormat::Sse);
+        assert_eq!(stream.repeat, Some(crate::event_stream::Repeat::Times(2)));
+        assert_eq!(stream.events.len(), 2);
+        assert_eq!(stream.events[0].event.as_deref(), Some("tick"));
+        assert_eq!(stream.events[0].data, r#"{"n": 1}"#);
+        assert_eq!(
+            stream.events[1].delay,
+            Some(Delay::Fixed(Duration::from_millis(500)))
+        );
+    }
+
+    #[test]
+    fn parse_websocket_routes_without_a_status() {
+        let example = r#"
+        WS /ws/chat
+        OnConnect={"type": "welcome"}
+        Reply=ping => pong
+        Echo=true
+
+        GET /api/rooms 200
+        "#;
+        let tokens = crate::tokenizer::tokens::parse(example.to_string()).unwrap();
+        let result = parse_requirements(&tokens).unwrap();
+
+        assert_eq!(result[0].http_response_status, 101);
+        let script = result[0].websocket.as_ref().unwrap();
+        assert_eq!(script.on_connect[0].data, r#"{"type": "welcome"}"#);
+        assert_eq!(script.replies[0].reply.data, "pong");
+        assert!(script.echo);
+        assert_eq!(result[1].path, "/api/rooms");
+
+        let tokens =
+            crate::tokenizer::tokens::parse("GET /api/rooms 200\nEcho=true".to_string()).unwrap();
+        assert!(matches!(
+            parse_requirements(&tokens),
+            Err(ParseError::NotAWebSocket(_))
+        ));
+    }
+
+    #[test]
+    fn parse_rejects_only_true_duplicates() {
+        let tokens = crate::tokenizer::tokens::parse(
+            "GET /users 200\nPOST /users 201\nWS /users".to_string(),
+        )
+        .unwrap();
+        assert_eq!(parse_requirements(&tokens).unwrap().len(), 3);
+
+        let tokens = crate::tokenizer::tokens::parse(
+            "GET /users 200\nPOST /users 201\nGET /users 404".to_string(),
+        )
+        .unwrap();
+        let result = parse_requirements(&tokens);
+        assert!(matches!(result, Err(ParseError::DuplicateRoute(route)) if route == "GET /users"));
+    }
+
+    #[test]
+    fn parse_path_patterns() {
+        let example = "GET \"/posts/:year(\\d{4})/:slug\" 200\nGET /files/*path 200\nGET /users/:id(\\d+)? 200";
+        let tokens = crate::tokenizer::tokens::parse(example.to_string()).unwrap();
+        let paths: Vec<String> = parse_requirements(&tokens)
+            .unwrap()
+            .into_iter()
+            .map(|route| route.path)
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                r"/posts/:year(\d{4})/:slug",
+                "/files/*path",
+                r"/users/:id(\d+)?"
+            ]
+        );
+
+        let tokens =
+            crate::tokenizer::tokens::parse("GET /files/*path/raw 200".to_string()).unwrap();
+        assert!(matches!(
+            parse_requirements(&tokens),
+            Err(ParseError::InvalidPath(_))
+        ));
+    }
+
+    #[test]
+    fn parse_fallback_blocks() {
+        let example = r#"
+        GET /users 200
+
+        FALLBACK 418
+        Header=X-Mock: missing
+        SuggestRoutes=true
+
+        {"error": "{{method}} {{path}} is not mocked"}
+        "#;
+        let tokens = crate::tokenizer::tokens::parse(example.to_string()).unwrap();
+        let file = parse_file(&tokens).unwrap();
+
+        assert_eq!(file.routes.len(), 1);
+        let fallback = file.fallback.unwrap();
+        assert_eq!(fallback.status, 418);
+        assert_eq!(
+            fallback.headers.get("X-Mock").map(String::as_str),
+            Some("missing")
+        );
+        assert!(fallback.suggest_routes);
+        assert_eq!(
+            fallback.body.as_deref(),
+            Some(r#"{"error":"{{method}} {{path}} is not mocked"}"#)
+        );
+
+        assert!(matches!(
+            parse_requirements(&tokens),
+            Err(ParseError::UnexpectedFallback)
+        ));
+
+        let tokens =
+            crate::tokenizer::tokens::parse("FALLBACK 404\nHeader=Bad Name: x".to_string())
+                .unwrap();
+        assert!(matches!(
+            parse_file(&tokens),
+            Err(ParseError::InvalidHeader(_))
+        ));
+    }
+
+    #[test]
+    fn parse_with_unknown_option() {
+        let tokens = vec![
+            Token::Identifier("GET".to_string()),
+            Token::Identifier("/foo".to_string()),
+            Token::Identifier("200".to_string()),
+            Token::Identifier("Dealy".to_string()),
+            Token::Equal,
+            Token::Identifier("1".to_string()),
+        ];
+        let result = parse_requirements(&tokens);
+        assert!(matches!(result, Err(ParseError::UnknownOption(_))));
+    }
+
+    #[test]
+    fn parse_rejects_invalid_delays() {
+        let tokens = vec![
+            Token::Identifier("GET".to_string()),
+            Token::Identifier("/foo".to_string()),
+            Token::Identifier("200".to_string()),
+            Token::Identifier("Delay".to_string()),
+            Token::Equal,
+            Token::Identifier("soon".to_string()),
+        ];
+        let result = parse_requirements(&tokens);
+        assert!(matches!(result, Err(ParseError::InvalidDelay(_))));
+    }
+}
@@ -1,2 +1,17 @@
 pub mod tokens;
 pub mod ast;
+pub mod writer;
+
+use crate::app_requirements::ApplicationRequirements;
+
+/// Parses the contents of a `.http` file into the routes it declares.
+pub fn parse_http(text: String) -> Result<Vec<ApplicationRequirements>, ast::ParseError> {
+    let tokens = tokens::parse(text)?;
+    ast::parse_requirements(&tokens)
+}
+
+/// Parses an imported `.http` file, which may also declare a `FALLBACK`.
+pub fn parse_http_file(text: String) -> Result<ast::HttpFile, ast::ParseError> {
+    let tokens = tokens::parse(text)?;
+    ast::parse_file(&tokens)
+}
@@ -1,5 +1,7 @@
 use std::{iter::Peekable, str::Chars, fmt};
 
+use super::ast::ParseError;
+
 /// Example
 /// GET /api/user/:id 200
 /// Delay=1
@@ -13,6 +15,8 @@ pub enum Token {
     Equal,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Quote,
     Colon,
     Comma,
@@ -25,6 +29,8 @@ impl fmt::Display for Token {
             Token::Equal => write!(f, "="),
             Token::LeftBrace => write!(f, "{{"),
             Token::RightBrace => write!(f, "}}"),
+            Token::LeftBracket => write!(f, "["),
+            Token::RightBracket => write!(f, "]"),
             Token::Quote => write!(f, "\""),
             Token::Colon => write!(f, ":"),
             Token::Comma => write!(f, ","),
@@ -32,25 +38,22 @@ impl fmt::Display for Token {
     }
 }
 
-pub fn parse(text: String) -> Vec<Token> {
+pub fn parse(text: String) -> Result<Vec<Token>, ParseError> {
     let mut peeks = text.chars().peekable();
     let mut tokens: Vec<Token> = vec![];
 
     while let Some(&symbol) = peeks.peek() {
         match symbol {
-            symbol if is_valid_string(symbol) => {
-                let content = read_string(&mut peeks);
-                if symbol == ':' {
-                    consume(&mut tokens, Token::Colon, &mut peeks, false);
-                } else {
-                    consume(&mut tokens, Token::Identifier(content), &mut peeks, false);
-                }
-            }
             symbol if symbol.is_whitespace() => {
                 peeks.next();
             }
             '=' => {
                 consume(&mut tokens, Token::Equal, &mut peeks, true);
+                // Option values run to the end of the line, e.g. `Header=Accept: */*`
+                let value = read_line(&mut peeks);
+                if !value.is_empty() {
+                    consume(&mut tokens, Token::Identifier(value), &mut peeks, false);
+                }
             }
             '{' => {
                 consume(&mut tokens, Token::LeftBrace, &mut peeks, true);
@@ -58,9 +61,15 @@ pub fn parse(text: String) -> Vec<Token> {
             '}' => {
                 consume(&mut tokens, Token::RightBrace, &mut peeks, true);
             }
+            '[' => {
+                consume(&mut tokens, Token::LeftBracket, &mut peeks, true);
+            }
+            ']' => {
+                consume(&mut tokens, Token::RightBracket, &mut peeks, true);
+            }
             '"' => {
                 consume(&mut tokens, Token::Quote, &mut peeks, true);
-                let content = read_quoted_string(&mut peeks);
+                let content = read_quoted_string(&mut peeks)?;
                 consume(&mut tokens, Token::Identifier(content), &mut peeks, false);
                 consume(&mut tokens, Token::Quote, &mut peeks, true);
             }
@@ -70,18 +79,21 @@ pub fn parse(text: String) -> Vec<Token> {
             ':' => {
                 consume(&mut tokens, Token::Colon, &mut peeks, true);
             }
-            _ => {
-                dbg!(tokens);
-                panic!("Invalid token -> [{symbol}]");
+            symbol if is_valid_string(symbol) => {
+                let content = read_string(&mut peeks);
+                consume(&mut tokens, Token::Identifier(content), &mut peeks, false);
             }
+            _ => return Err(ParseError::InvalidToken(symbol)),
         }
     }
 
-    tokens
+    Ok(tokens)
 }
 
 fn is_valid_string(ch: char) -> bool {
-    ch.is_alphanumeric() || ch == '/' || ch == ':'
+    !ch.is_whitespace()
+        && !ch.is_control()
+        && !matches!(ch, '=' | '{' | '}' | '[' | ']' | '"' | ',')
 }
 
 fn read_string(peeks: &mut Peekable<Chars<'_>>) -> String {
@@ -96,21 +108,43 @@ fn read_string(peeks: &mut Peekable<Chars<'_>>) -> String {
         }
     }
 
-    return string;
+    string
 }
 
-fn read_quoted_string(peeks: &mut Peekable<Chars<'_>>) -> String {
+/// Reads up to the closing quote, keeping escape sequences as written so the
+/// body can be put back together verbatim.
+fn read_quoted_string(peeks: &mut Peekable<Chars<'_>>) -> Result<String, ParseError> {
     let mut string = String::new();
 
     while let Some(&ch) = peeks.peek() {
         if ch == '"' {
-            break;
+            return Ok(string);
         }
         string.push(ch);
         peeks.next();
+
+        if ch == '\\' {
+            if let Some(escaped) = peeks.next() {
+                string.push(escaped);
+            }
+        }
     }
 
-    return string;
+    Err(ParseError::UnterminatedString(string))
+}
+
+fn read_line(peeks: &mut Peekable<Chars<'_>>) -> String {
+    let mut line = String::new();
+
+    while let Some(&ch) = peeks.peek() {
+        if ch == '\n' {
+            break;
+        }
+        line.push(ch);
+        peeks.next();
+    }
+
+    line.trim().to_string()
 }
 
 fn consume(tokens: &mut Vec<Token>, token: Token, peeks: &mut Peekable<Chars<'_>>, should_peek: bool) {
@@ -139,7 +173,7 @@ mod tests {
         .to_string();
 
         assert_eq!(
-            parse(example),
+            parse(example).unwrap(),
             vec![
                 Token::Identifier("GET".to_string()),
                 Token::Identifier("/api/user/:id".to_string()),
@@ -171,7 +205,7 @@ mod tests {
         .to_string();
 
         assert_eq!(
-            parse(example),
+            parse(example).unwrap(),
             vec![
                 Token::Identifier("PUT".to_string()),
                 Token::Identifier("/foo".to_string()),
@@ -205,7 +239,7 @@ mod tests {
         .to_string();
 
         assert_eq!(
-            parse(example),
+            parse(example).unwrap(),
             vec![
                 Token::LeftBrace,
                 Token::Quote,
@@ -219,4 +253,63 @@ mod tests {
             ]
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn parse_nested_json_with_arrays_and_escapes() {
+        let example = r#"
+        GET /api/users-list 200
+        Header=Content-Type: application/json; charset=utf-8
+
+        {"users":[{"id":-1.5,"tags":[]}],"quote":"say \"hi\""}
+        "#
+        .to_string();
+
+        assert_eq!(
+            parse(example).unwrap(),
+            vec![
+                Token::Identifier("GET".to_string()),
+                Token::Identifier("/api/users-list".to_string()),
+                Token::Identifier("200".to_string()),
+                Token::Identifier("Header".to_string()),
+                Token::Equal,
+                Token::Identifier("Content-Type: application/json; charset=utf-8".to_string()),
+                Token::LeftBrace,
+                Token::Quote,
+                Token::Identifier("users".to_string()),
+                Token::Quote,
+                Token::Colon,
+                Token::LeftBracket,
+                Token::LeftBrace,
+                Token::Quote,
+                Token::Identifier("id".to_string()),
+                Token::Quote,
+                Token::Colon,
+                Token::Identifier("-1.5".to_string()),
+                Token::Comma,
+                Token::Quote,
+                Token::Identifier("tags".to_string()),
+                Token::Quote,
+                Token::Colon,
+                Token::LeftBracket,
+                Token::RightBracket,
+                Token::RightBrace,
+                Token::RightBracket,
+                Token::Comma,
+                Token::Quote,
+                Token::Identifier("quote".to_string()),
+                Token::Quote,
+                Token::Colon,
+                Token::Quote,
+                Token::Identifier(r#"say \"hi\""#.to_string()),
+                Token::Quote,
+                Token::RightBrace
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_unterminated_string() {
+        let result = parse(r#"{ "foo: true }"#.to_string());
+        assert!(matches!(result, Err(ParseError::UnterminatedString(_))));
+    }
+}
@@ -0,0 +1,213 @@
+use crate::{app_requirements::ApplicationRequirements, cors::CorsSettings};
+
+/// Writes routes back in the `.http` format, so `parse_http` reads them as they were.
+pub fn write_requirements(requirements: &[ApplicationRequirements]) -> String {
+    requirements
+        .iter()
+        .map(write_requirement)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Quotes the paths the tokenizer would otherwise split, e.g. `/posts/:year(\d{4})`.
+fn write_path(path: &str) -> String {
+    if path.contains(['{', '}', '[', ']', ',', '=']) {
+        format!("\"{path}\"")
+    } else {
+        path.to_string()
+    }
+}
+
+fn write_requirement(requirement: &ApplicationRequirements) -> String {
+    let mut block = format!(
+        "{} {} {}\n",
+        requirement.http_method,
+        write_path(&requirement.path),
+        requirement.http_response_status
+    );
+
+    if let Some(priority) = requirement.priority {
+        block.push_str(&format!("Priority={priority}\n"));
+    }
+    if let Some(delay) = &requirement.delay {
+        block.push_str(&format!("Delay={delay}\n"));
+    }
+    if let Some(throttle) = &requirement.throttle {
+        block.push_str(&format!("Throttle={throttle}\n"));
+    }
+    if let Some(first_byte) = &requirement.first_byte {
+        block.push_str(&format!("FirstByte={first_byte}\n"));
+    }
+    if let Some(fault) = &requirement.fault {
+        block.push_str(&format!("Fault={fault}\n"));
+    }
+    for (name, value) in &requirement.http_response_headers {
+        block.push_str(&format!("Header={name}: {value}\n"));
+    }
+    if let Some(path) = &requirement.http_response_path {
+        block.push_str(&format!("File={path}\n"));
+    }
+    if let Some(subject) = &requirement.client_subject {
+        block.push_str(&format!("ClientCert={subject}\n"));
+    }
+    if let Some(cors) = &requirement.cors {
+        write_cors(&mut block, cors);
+    }
+    if let Some(script) = &requirement.websocket {
+        for message in &script.on_connect {
+            block.push_str(&format!("OnConnect={message}\n"));
+        }
+        for rule in &script.replies {
+            block.push_str(&format!("Reply={rule}\n"));
+        }
+        for push in &script.pushes {
+            block.push_str(&format!("Push={push}\n"));
+        }
+        if script.echo {
+            block.push_str("Echo=true\n");
+        }
+    }
+    if let Some(stream) = &requirement.stream {
+        block.push_str(&format!("Stream={}\n", stream.format));
+        if let Some(repeat) = stream.repeat {
+            block.push_str(&format!("Repeat={repeat}\n"));
+        }
+        for event in &stream.events {
+            block.push_str(&format!("Event={event}\n"));
+        }
+    }
+
+    if let Some(body) = &requirement.http_response_body {
+        let pretty = serde_json::from_str::<serde_json::Value>(body)
+            .and_then(|json| serde_json::to_string_pretty(&json));
+        block.push('\n');
+        block.push_str(pretty.as_deref().unwrap_or(body));
+        block.push('\n');
+    }
+
+    block
+}
+
+fn write_cors(block: &mut String, cors: &CorsSettings) {
+    if cors == &CorsSettings::default() {
+        block.push_str("CorsOrigin=*\n");
+    }
+    for origin in &cors.origins {
+        block.push_str(&format!("CorsOrigin={origin}\n"));
+    }
+    if !cors.methods.is_empty() {
+        block.push_str(&format!("CorsMethods={}\n", cors.methods.join(", ")));
+    }
+    if !cors.headers.is_empty() {
+        block.push_str(&format!("CorsHeaders={}\n", cors.headers.join(", ")));
+    }
+    if let Some(credentials) = cors.credentials {
+        block.push_str(&format!("CorsCredentials={credentials}\n"));
+    }
+    if let Some(max_age) = cors.max_age {
+        block.push_str(&format!("CorsMaxAge={max_age}\n"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        app_requirements::HttpMethods,
+        event_stream::{EventStream, Repeat, StreamFormat},
+        fault::Fault,
+        tokenizer::parse_http,
+        websocket::WebSocketScript,
+    };
+
+    #[test]
+    fn written_requirements_parse_back_the_same() {
+        let requirements = vec![
+            ApplicationRequirements {
+                path: "/api/users".to_string(),
+                http_method: HttpMethods::GET,
+                http_response_status: 200,
+                http_response_body: Some(
+                    r#"[{"id":1,"name":"Foo","tags":["a-b"]},{"id":2,"score":-0.5}]"#.to_string(),
+                ),
+                http_response_headers: [(
+                    "content-type".to_string(),
+                    "application/json".to_string(),
+                )]
+                .into(),
+                delay: Some("percentiles(p50=100ms, p99=1.5s)".parse().unwrap()),
+                cors: Some(CorsSettings {
+                    origins: vec![
+                        "http://localhost:3000".to_string(),
+                        "http://localhost:5173".to_string(),
+                    ],
+                    headers: vec!["Content-Type".to_string(), "Authorization".to_string()],
+                    credentials: Some(true),
+                    max_age: Some(600),
+                    ..CorsSettings::default()
+                }),
+                ..ApplicationRequirements::default()
+            },
+            ApplicationRequirements {
+                path: "/api/users/:id/avatar".to_string(),
+                http_method: HttpMethods::GET,
+                http_response_status: 200,
+                http_response_path: Some("routes.bodies/avatar.png".to_string()),
+                throttle: Some("16kb".parse().unwrap()),
+                fault: Some(Fault::MalformedChunks),
+                first_byte: Some("uniform(100ms, 300ms)".parse().unwrap()),
+                client_subject: Some("CN=alice, O=Acme".to_string()),
+                ..ApplicationRequirements::default()
+            },
+            ApplicationRequirements {
+                path: "/api/users/:id".to_string(),
+                http_method: HttpMethods::DELETE,
+                http_response_status: 204,
+                ..ApplicationRequirements::default()
+            },
+            ApplicationRequirements {
+                path: r"/api/users/:id(\d{1,6})/*rest".to_string(),
+                http_method: HttpMethods::Custom("PROPFIND".to_string()),
+                http_response_status: 207,
+                priority: Some(-2),
+                ..ApplicationRequirements::default()
+            },
+            ApplicationRequirements {
+                path: "/api/feed".to_string(),
+                http_method: HttpMethods::GET,
+                http_response_status: 200,
+                stream: Some(EventStream {
+                    format: StreamFormat::Ndjson,
+                    events: vec![
+                        r#"{"n": 1}"#.parse().unwrap(),
+                        "delay=uniform(100ms, 200ms); id=2; event=tick; data=two; three"
+                            .parse()
+                            .unwrap(),
+                    ],
+                    repeat: Some(Repeat::Forever),
+                }),
+                ..ApplicationRequirements::default()
+            },
+            ApplicationRequirements {
+                path: "/ws/chat".to_string(),
+                http_method: HttpMethods::WS,
+                http_response_status: 101,
+                websocket: Some(WebSocketScript {
+                    on_connect: vec![
+                        r#"{"type": "welcome"}"#.parse().unwrap(),
+                        "delay=1s; data=data=odd".parse().unwrap(),
+                    ],
+                    replies: vec![r#"{"type": "ping"} => delay=100ms; data={"type": "pong"}"#
+                        .parse()
+                        .unwrap()],
+                    pushes: vec!["every=normal(5s, 1s); data=heartbeat".parse().unwrap()],
+                    echo: true,
+                }),
+                ..ApplicationRequirements::default()
+            },
+        ];
+
+        let written = write_requirements(&requirements);
+        assert_eq!(parse_http(written).unwrap(), requirements);
+    }
+}
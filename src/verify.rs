@@ -0,0 +1,378 @@
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{header, Method, Request},
+};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    admin::ADMIN_PREFIX,
+    client,
+    journal::{Journal, JournalEntry},
+    tls,
+};
+
+/// How many near misses a failed verification reports.
+const NEAR_MISS_LIMIT: usize = 3;
+
+/// Criteria a journaled request must meet, and how many of them are expected.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Verification {
+    pub method: Option<String>,
+    /// Either the requested path or the pattern of the route that matched it.
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    /// A JSON body is compared structurally, a string body byte for byte.
+    pub body: Option<serde_json::Value>,
+    pub times: Option<u64>,
+    pub at_least: Option<u64>,
+    pub at_most: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NearMiss {
+    pub request: JournalEntry,
+    pub mismatches: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerificationResult {
+    pub passed: bool,
+    pub expected: String,
+    pub count: u64,
+    /// The requests closest to the criteria, filled in when the count is wrong.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub near_misses: Vec<NearMiss>,
+}
+
+impl Verification {
+    pub fn run(&self, journal: &Journal) -> VerificationResult {
+        let mut count = 0;
+        let mut near_misses = Vec::new();
+
+        for entry in &journal.entries {
+            let mismatches = self.mismatches(entry);
+            if mismatches.is_empty() {
+                count += 1;
+            } else {
+                near_misses.push(NearMiss {
+                    request: entry.clone(),
+                    mismatches,
+                });
+            }
+        }
+
+        let passed = self.count_matches(count);
+        if passed {
+            near_misses.clear();
+        } else {
+            // Most recent first among equally close requests.
+            near_misses.reverse();
+            near_misses.sort_by_key(|miss| miss.mismatches.len());
+            near_misses.truncate(NEAR_MISS_LIMIT);
+        }
+
+        VerificationResult {
+            passed,
+            expected: self.expected(),
+            count,
+            near_misses,
+        }
+    }
+
+    fn count_matches(&self, count: u64) -> bool {
+        match (self.times, self.at_least, self.at_most) {
+            (Some(times), _, _) => count == times,
+            (None, None, None) => count >= 1,
+            (None, at_least, at_most) => {
+                at_least.is_none_or(|min| count >= min) && at_most.is_none_or(|max| count <= max)
+            }
+        }
+    }
+
+    fn expected(&self) -> String {
+        match (self.times, self.at_least, self.at_most) {
+            (Some(times), _, _) => format!("exactly {times}"),
+            (None, Some(min), Some(max)) => format!("between {min} and {max}"),
+            (None, Some(min), None) => format!("at least {min}"),
+            (None, None, Some(max)) => format!("at most {max}"),
+            (None, None, None) => "at least 1".to_string(),
+        }
+    }
+
+    fn mismatches(&self, entry: &JournalEntry) -> Vec<String> {
+        let mut mismatches = Vec::new();
+
+        if let Some(method) = &self.method {
+            if !method.eq_ignore_ascii_case(&entry.method) {
+                mismatches.push(format!("method: expected {method}, got {}", entry.method));
+            }
+        }
+
+        if let Some(path) = &self.path {
+            if *path != entry.path && entry.matched_route.as_ref() != Some(path) {
+                mismatches.push(format!("path: expected {path}, got {}", entry.path));
+            }
+        }
+
+        for (name, value) in &self.headers {
+            match entry.headers.get(&name.to_ascii_lowercase()) {
+                Some(actual) if actual == value => {}
+                Some(actual) => {
+                    mismatches.push(format!("header {name}: expected {value:?}, got {actual:?}"))
+                }
+                None => mismatches.push(format!("header {name}: missing")),
+            }
+        }
+
+        if let Some(body) = &self.body {
+            let matches = match body {
+                serde_json::Value::String(body) => *body == entry.body,
+                body => serde_json::from_str::<serde_json::Value>(&entry.body)
+                    .is_ok_and(|actual| actual == *body),
+            };
+            if !matches {
+                mismatches.push(format!("body: expected {body}, got {:?}", entry.body));
+            }
+        }
+
+        mismatches
+    }
+}
+
+/// Asserts on the requests a running testroute received.
+#[derive(Args, Debug)]
+pub struct VerifyArgs {
+    /// Address of the running testroute
+    #[arg(long, default_value = "http://localhost:9999")]
+    url: String,
+
+    #[arg(short, long)]
+    method: Option<String>,
+
+    #[arg(short, long)]
+    path: Option<String>,
+
+    /// Header the request must carry, as `Name: value` (repeatable)
+    #[arg(short = 'H', long = "header")]
+    headers: Vec<String>,
+
+    /// Expected body, compared structurally when it is valid JSON
+    #[arg(short, long)]
+    body: Option<String>,
+
+    #[arg(long)]
+    times: Option<u64>,
+
+    #[arg(long)]
+    at_least: Option<u64>,
+
+    #[arg(long)]
+    at_most: Option<u64>,
+
+    /// Trust the certificates signed by these CAs (PEM) for an https URL
+    #[arg(long, value_name = "PEM")]
+    cacert: Option<PathBuf>,
+
+    /// Accept any certificate for an https URL, e.g. the self-signed one of `--tls`
+    #[arg(short = 'k', long)]
+    insecure: bool,
+}
+
+impl VerifyArgs {
+    fn into_verification(self) -> Result<Verification, String> {
+        let headers = self
+            .headers
+            .iter()
+            .map(|header| {
+                header
+                    .split_once(':')
+                    .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+                    .ok_or_else(|| format!("Invalid header, expected `Name: value`: {header}"))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Verification {
+            method: self.method,
+            path: self.path,
+            headers,
+            body: self
+                .body
+                .map(|body| serde_json::from_str(&body).unwrap_or(serde_json::Value::String(body))),
+            times: self.times,
+            at_least: self.at_least,
+            at_most: self.at_most,
+        })
+    }
+}
+
+/// Runs the `verify` subcommand, returning the process exit code.
+pub async fn run_cli(args: VerifyArgs) -> i32 {
+    let url = format!("{}{ADMIN_PREFIX}/verify", args.url.trim_end_matches('/'));
+    let tls_config = if url.starts_with("https://") {
+        if args.cacert.is_none() && !args.insecure {
+            eprintln!("Verifying against {url} needs --cacert, or --insecure for a self-signed certificate");
+            return 2;
+        }
+        match tls::client_config(args.cacert.as_deref(), args.insecure) {
+            Ok(config) => Some(Arc::new(config)),
+            Err(err) => {
+                eprintln!("{err}");
+                return 2;
+            }
+        }
+    } else {
+        None
+    };
+
+    let verification = match args.into_verification() {
+        Ok(verification) => verification,
+        Err(err) => {
+            eprintln!("{err}");
+            return 2;
+        }
+    };
+
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri(&url)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_vec(&verification).unwrap()))
+        .unwrap();
+
+    let result = match client::send_once(request, tls_config).await {
+        Ok(response) => to_bytes(Body::new(response.into_body()), usize::MAX)
+            .await
+            .map_err(|err| err.to_string())
+            .and_then(|body| {
+                serde_json::from_slice::<VerificationResult>(&body).map_err(|err| err.to_string())
+            }),
+        Err(err) => Err(err),
+    };
+
+    let result = match result {
+        Ok(result) => result,
+        Err(err) => {
+            eprintln!("Could not verify against {url}: {err}");
+            return 2;
+        }
+    };
+
+    if result.passed {
+        println!("PASSED: expected {}, got {}", result.expected, result.count);
+        return 0;
+    }
+
+    println!("FAILED: expected {}, got {}", result.expected, result.count);
+    if !result.near_misses.is_empty() {
+        println!("Closest requests:");
+    }
+    for miss in &result.near_misses {
+        println!(
+            "  #{} {} {}",
+            miss.request.id, miss.request.method, miss.request.uri
+        );
+        for mismatch in &miss.mismatches {
+            println!("    - {mismatch}");
+        }
+    }
+
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, path: &str, body: &str) -> JournalEntry {
+        JournalEntry {
+            id: 0,
+            timestamp_ms: 0,
+            method: method.to_string(),
+            uri: path.to_string(),
+            version: "HTTP/1.1".to_string(),
+            path: path.to_string(),
+            headers: BTreeMap::from([("content-type".to_string(), "application/json".to_string())]),
+            body: body.to_string(),
+            body_truncated: false,
+            matched_route: None,
+            status: 200,
+            client_subject: None,
+        }
+    }
+
+    fn journal(requests: Vec<JournalEntry>) -> Journal {
+        let mut journal = Journal::default();
+        for request in requests {
+            journal.push(request, 100);
+        }
+        journal
+    }
+
+    #[test]
+    fn verify_counts_requests_matching_every_criterion() {
+        let journal = journal(vec![
+            request("POST", "/api/orders", r#"{"id": 1, "items": []}"#),
+            request("POST", "/api/orders", r#"{"id": 2}"#),
+            request("GET", "/api/orders", ""),
+        ]);
+
+        let verification = Verification {
+            method: Some("POST".to_string()),
+            path: Some("/api/orders".to_string()),
+            headers: BTreeMap::from([("Content-Type".to_string(), "application/json".to_string())]),
+            body: Some(serde_json::json!({"items": [], "id": 1})),
+            times: Some(1),
+            ..Verification::default()
+        };
+
+        let result = verification.run(&journal);
+        assert!(result.passed);
+        assert_eq!(result.count, 1);
+        assert!(result.near_misses.is_empty());
+    }
+
+    #[test]
+    fn failed_verification_reports_the_closest_requests() {
+        let journal = journal(vec![
+            request("GET", "/api/users", ""),
+            request("POST", "/api/orders", r#"{"id": 2}"#),
+        ]);
+
+        let verification = Verification {
+            method: Some("POST".to_string()),
+            path: Some("/api/orders".to_string()),
+            body: Some(serde_json::json!({"id": 1})),
+            ..Verification::default()
+        };
+
+        let result = verification.run(&journal);
+        assert!(!result.passed);
+        assert_eq!(result.expected, "at least 1");
+        assert_eq!(result.near_misses.len(), 2);
+        assert_eq!(result.near_misses[0].request.path, "/api/orders");
+        assert_eq!(result.near_misses[0].mismatches.len(), 1);
+    }
+
+    #[test]
+    fn verify_supports_count_ranges() {
+        let journal = journal(vec![request("GET", "/a", ""), request("GET", "/a", "")]);
+
+        let at_most_one = Verification {
+            path: Some("/a".to_string()),
+            at_most: Some(1),
+            ..Verification::default()
+        };
+        assert!(!at_most_one.run(&journal).passed);
+
+        let never = Verification {
+            path: Some("/b".to_string()),
+            times: Some(0),
+            ..Verification::default()
+        };
+        assert!(never.run(&journal).passed);
+    }
+}
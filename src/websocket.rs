@@ -0,0 +1,376 @@
+use std::{fmt, str::FromStr, sync::Arc};
+
+use axum::extract::ws::{Message, WebSocket};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use thiserror::Error;
+use tokio::{sync::mpsc, task::JoinSet};
+
+use crate::{
+    delay::{Delay, Jitter},
+    event_stream::deserialize_data,
+};
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+#[error("Invalid WebSocket message, expected e.g. `delay=500ms; data=hello` or `hello`: {0}")]
+pub struct InvalidMessage(pub String);
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+#[error("Invalid reply rule, expected `<client frame> => <reply>`: {0}")]
+pub struct InvalidReply(pub String);
+
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+#[error("Invalid push, expected e.g. `every=5s; data=ping`: {0}")]
+pub struct InvalidPush(pub String);
+
+/// Frames waiting to be sent on one connection; timers wait for room past it.
+const QUEUED_FRAMES: usize = 32;
+
+/// Splits the `name=` field off the front of `delay=500ms; data=hello`,
+/// returning its value and what follows the `;`.
+fn leading_field<'a>(value: &'a str, name: &str) -> Option<(&'a str, &'a str)> {
+    let rest = value.trim().strip_prefix(name)?.strip_prefix('=')?;
+    let (field, rest) = rest.split_once(';').unwrap_or((rest, ""));
+    Some((field.trim(), rest.trim()))
+}
+
+/// The data of a message, `data=` being optional unless the data itself
+/// looks like a field.
+fn parse_data(value: &str) -> String {
+    let value = value.trim();
+    value
+        .strip_prefix("data=")
+        .unwrap_or(value)
+        .trim()
+        .to_string()
+}
+
+fn write_data(f: &mut fmt::Formatter<'_>, data: &str, has_fields: bool) -> fmt::Result {
+    let looks_like_field = ["delay=", "every=", "data="]
+        .iter()
+        .any(|field| data.starts_with(field));
+    match (has_fields, looks_like_field) {
+        (false, false) => write!(f, "{data}"),
+        (false, true) => write!(f, "data={data}"),
+        (true, _) => write!(f, "; data={data}"),
+    }
+}
+
+/// A text frame the server sends, after an optional delay.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SocketMessage {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delay: Option<Delay>,
+    #[serde(deserialize_with = "deserialize_data")]
+    pub data: String,
+}
+
+impl FromStr for SocketMessage {
+    type Err = InvalidMessage;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let Some((delay, rest)) = leading_field(value, "delay") else {
+            return Ok(SocketMessage {
+                delay: None,
+                data: parse_data(value),
+            });
+        };
+
+        Ok(SocketMessage {
+            delay: Some(
+                delay
+                    .parse()
+                    .map_err(|_| InvalidMessage(value.to_string()))?,
+            ),
+            data: parse_data(rest),
+        })
+    }
+}
+
+impl fmt::Display for SocketMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(delay) = &self.delay {
+            write!(f, "delay={delay}")?;
+        }
+        write_data(f, &self.data, self.delay.is_some())
+    }
+}
+
+/// Answers client frames matching `when`: the same text, or JSON holding at
+/// least the fields of `when` with the same values.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ReplyRule {
+    #[serde(deserialize_with = "deserialize_data")]
+    pub when: String,
+    pub reply: SocketMessage,
+}
+
+impl ReplyRule {
+    fn matches(&self, frame: &str) -> bool {
+        if frame.trim() == self.when.trim() {
+            return true;
+        }
+
+        match (
+            serde_json::from_str::<serde_json::Value>(&self.when),
+            serde_json::from_str::<serde_json::Value>(frame),
+        ) {
+            (Ok(pattern), Ok(frame)) => json_contains(&frame, &pattern),
+            _ => false,
+        }
+    }
+}
+
+fn json_contains(value: &serde_json::Value, pattern: &serde_json::Value) -> bool {
+    match (value, pattern) {
+        (serde_json::Value::Object(value), serde_json::Value::Object(pattern)) => {
+            pattern.iter().all(|(key, expected)| {
+                value
+                    .get(key)
+                    .is_some_and(|actual| json_contains(actual, expected))
+            })
+        }
+        _ => value == pattern,
+    }
+}
+
+/// Parses `{"type": "ping"} => {"type": "pong"}`.
+impl FromStr for ReplyRule {
+    type Err = InvalidReply;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidReply(value.to_string());
+        let (when, reply) = value.split_once("=>").ok_or_else(invalid)?;
+        if when.trim().is_empty() {
+            return Err(invalid());
+        }
+
+        Ok(ReplyRule {
+            when: when.trim().to_string(),
+            reply: reply.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl fmt::Display for ReplyRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} => {}", self.when, self.reply)
+    }
+}
+
+/// A frame the server sends again and again, e.g. a heartbeat.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Push {
+    #[serde(deserialize_with = "deserialize_every")]
+    pub every: Delay,
+    #[serde(deserialize_with = "deserialize_data")]
+    pub data: String,
+}
+
+/// Parses `every=5s; data=ping`.
+impl FromStr for Push {
+    type Err = InvalidPush;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidPush(value.to_string());
+        let (every, rest) = leading_field(value, "every").ok_or_else(invalid)?;
+
+        Ok(Push {
+            every: every
+                .parse::<Delay>()
+                .ok()
+                .filter(|every| !every.is_zero())
+                .ok_or_else(invalid)?,
+            data: parse_data(rest),
+        })
+    }
+}
+
+/// A push repeating every `0s` would flood the client.
+fn deserialize_every<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Delay, D::Error> {
+    let every = Delay::deserialize(deserializer)?;
+    if every.is_zero() {
+        return Err(de::Error::custom("a push can't repeat every 0s"));
+    }
+    Ok(every)
+}
+
+impl fmt::Display for Push {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "every={}", self.every)?;
+        write_data(f, &self.data, true)
+    }
+}
+
+/// What a `WS` route does once the client is connected.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct WebSocketScript {
+    /// Sent one after the other as soon as the client connects.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_connect: Vec<SocketMessage>,
+    /// The first rule matching a client frame answers it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replies: Vec<ReplyRule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pushes: Vec<Push>,
+    /// Sends back the frames no rule matches.
+    #[serde(default)]
+    pub echo: bool,
+}
+
+impl WebSocketScript {
+    /// Plays the script on `socket` until the client disconnects.
+    pub async fn run(self, mut socket: WebSocket, jitter: Arc<Jitter>) {
+        let (outgoing, mut queued) = mpsc::channel(QUEUED_FRAMES);
+        // Dropping the set when the client leaves stops the pending timers.
+        let mut timers = JoinSet::new();
+
+        timers.spawn(send_after_delays(
+            self.on_connect.clone(),
+            outgoing.clone(),
+            jitter.clone(),
+        ));
+        for push in &self.pushes {
+            let (push, outgoing, jitter) = (push.clone(), outgoing.clone(), jitter.clone());
+            timers.spawn(async move {
+                loop {
+                    tokio::time::sleep(jitter.sample(&push.every)).await;
+                    if outgoing
+                        .send(Message::Text(push.data.clone()))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+            });
+        }
+
+        loop {
+            tokio::select! {
+                Some(message) = queued.recv() => {
+                    if socket.send(message).await.is_err() {
+                        break;
+                    }
+                }
+                // Reaps the replies already sent.
+                Some(_) = timers.join_next() => {}
+                frame = socket.recv() => {
+                    let echo = match frame {
+                        Some(Ok(Message::Text(text))) => {
+                            match self.replies.iter().find(|rule| rule.matches(&text)) {
+                                Some(rule) => {
+                                    let reply = vec![rule.reply.clone()];
+                                    timers.spawn(send_after_delays(reply, outgoing.clone(), jitter.clone()));
+                                    None
+                                }
+                                None => self.echo.then_some(Message::Text(text)),
+                            }
+                        }
+                        Some(Ok(Message::Binary(bytes))) => {
+                            self.echo.then_some(Message::Binary(bytes))
+                        }
+                        Some(Ok(Message::Close(_)) | Err(_)) | None => break,
+                        // Pings are answered by the WebSocket implementation itself.
+                        Some(Ok(_)) => None,
+                    };
+                    // Sent right away: waiting for room in the queue here would keep
+                    // it from being emptied.
+                    if let Some(echo) = echo {
+                        if socket.send(echo).await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn send_after_delays(
+    messages: Vec<SocketMessage>,
+    outgoing: mpsc::Sender<Message>,
+    jitter: Arc<Jitter>,
+) {
+    for message in messages {
+        if let Some(delay) = &message.delay {
+            tokio::time::sleep(jitter.sample(delay)).await;
+        }
+        if outgoing.send(Message::Text(message.data)).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::WebSocketUpgrade, routing::get, Router};
+    use futures_util::{SinkExt, StreamExt};
+    use std::time::Duration;
+    use tokio_tungstenite::tungstenite;
+
+    #[test]
+    fn options_parse_and_write_back() {
+        let message: SocketMessage = "delay=500ms; data=hello".parse().unwrap();
+        assert_eq!(
+            message.delay,
+            Some(Delay::Fixed(Duration::from_millis(500)))
+        );
+        assert_eq!(message.data, "hello");
+        assert_eq!(message.to_string(), "delay=500ms; data=hello");
+        assert_eq!(
+            "hello".parse::<SocketMessage>().unwrap().to_string(),
+            "hello"
+        );
+
+        let rule: ReplyRule = r#"{"type": "ping"} => {"type": "pong"}"#.parse().unwrap();
+        assert_eq!(rule.to_string().parse::<ReplyRule>().unwrap(), rule);
+        assert!(rule.matches(r#"{"type": "ping", "id": 4}"#));
+        assert!(!rule.matches(r#"{"type": "join"}"#));
+        assert!("no arrow".parse::<ReplyRule>().is_err());
+
+        let push: Push = "every=5s; data=heartbeat".parse().unwrap();
+        assert_eq!(push.to_string(), "every=5s; data=heartbeat");
+        assert!("data=heartbeat".parse::<Push>().is_err());
+        assert!("every=0s; data=heartbeat".parse::<Push>().is_err());
+        assert!(serde_json::from_str::<Push>(r#"{"every": "0ms", "data": "heartbeat"}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn scripts_greet_reply_and_echo() {
+        let script = WebSocketScript {
+            on_connect: vec!["welcome".parse().unwrap()],
+            replies: vec!["ping => pong".parse().unwrap()],
+            echo: true,
+            ..WebSocketScript::default()
+        };
+        let router = Router::new().route(
+            "/chat",
+            get(move |upgrade: WebSocketUpgrade| async move {
+                upgrade.on_upgrade(move |socket| script.run(socket, Arc::default()))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let (mut client, _) = tokio_tungstenite::connect_async(format!("ws://{address}/chat"))
+            .await
+            .unwrap();
+        assert_eq!(
+            client.next().await.unwrap().unwrap().into_text().unwrap(),
+            "welcome"
+        );
+
+        for (sent, expected) in [("ping", "pong"), ("anyone?", "anyone?")] {
+            client
+                .send(tungstenite::Message::Text(sent.to_string()))
+                .await
+                .unwrap();
+            assert_eq!(
+                client.next().await.unwrap().unwrap().into_text().unwrap(),
+                expected
+            );
+        }
+    }
+}